@@ -0,0 +1,10 @@
+// Darkens the edges of every display pixel, like the gaps on an LCD
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	let color = textureSample(t_input, s_input, in.tex_coords);
+	let cell = fract(in.tex_coords * post.source_size);
+	let edge = min(min(cell.x, 1.0 - cell.x), min(cell.y, 1.0 - cell.y));
+	// About a pixel of the output wide
+	let gap = 0.5 * post.source_size.x / post.resolution.x;
+	return vec4<f32>(color.rgb * select(0.6, 1.0, edge > gap), 1.0);
+}
@@ -0,0 +1,6 @@
+// Sways the rows of the display from side to side
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	let offset = sin(in.tex_coords.y * 12.0 + post.time * 3.0) * 0.01;
+	return textureSample(t_input, s_input, vec2<f32>(in.tex_coords.x + offset, in.tex_coords.y));
+}
@@ -0,0 +1,457 @@
+use crate::chip8::Chip8;
+use crate::config::{Config, RecordFormat, Upscaler};
+use crate::palette::Palette;
+use crate::renderer::{display_rgba, frame_blender, upscale, FrameBlender};
+use crate::timing::INSTRUCTIONS_PER_FRAME;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, PartialEq)]
+struct Image {
+    width: usize,
+    height: usize,
+    // RGBA
+    pixels: Vec<u8>,
+}
+
+impl Image {
+    // What the window shows, the blender's last frame or else the screen
+    fn display(chip8: &Chip8, palette: &Palette, blender: Option<&FrameBlender>) -> Self {
+        if let Some((width, height, pixels)) = blender.and_then(FrameBlender::frame) {
+            return Self { width, height, pixels: pixels.to_vec() };
+        }
+        let (width, height, pixels) = display_rgba(chip8, palette);
+        Self { width, height, pixels }
+    }
+
+    // Nearest neighbour, so the display's pixels stay sharp
+    fn resize(&self, width: usize, height: usize) -> Self {
+        if (width, height) == (self.width, self.height) {
+            return self.clone();
+        }
+        let mut pixels = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            let row = y * self.height / height * self.width;
+            for x in 0..width {
+                let offset = (row + x * self.width / width) * 4;
+                pixels.extend_from_slice(&self.pixels[offset..offset + 4]);
+            }
+        }
+        Self { width, height, pixels }
+    }
+
+    // Scales up by the largest whole factor that fits in `width` by `height` and centers the
+    // result on `fill`, like --scale integer does in the window
+    fn fit(&self, width: usize, height: usize, fill: [u8; 4]) -> Self {
+        if (width, height) == (self.width, self.height) {
+            return self.clone();
+        }
+        if self.width > width || self.height > height {
+            return self.resize(width, height);
+        }
+        let factor = (width / self.width).min(height / self.height);
+        let scaled = self.resize(self.width * factor, self.height * factor);
+        let (left, top) = ((width - scaled.width) / 2, (height - scaled.height) / 2);
+        let mut pixels = fill.repeat(width * height);
+        for (y, row) in scaled.pixels.chunks_exact(scaled.width * 4).enumerate() {
+            let offset = ((top + y) * width + left) * 4;
+            pixels[offset..offset + row.len()].copy_from_slice(row);
+        }
+        Self { width, height, pixels }
+    }
+}
+
+// How captures are drawn from the display's pixels
+#[derive(Debug, Copy, Clone)]
+struct Look {
+    upscaler: Upscaler,
+    // Output pixels per display pixel
+    scale: usize,
+}
+
+impl Look {
+    fn draw(self, image: &Image) -> Image {
+        let (width, height, pixels) = upscale(self.upscaler, image.width, image.height, &image.pixels);
+        // Relative to the display, so a capture is the same size whichever upscaler is used
+        Image { width, height, pixels }.resize(image.width * self.scale, image.height * self.scale)
+    }
+}
+
+// Takes screenshots and recordings of the display the way the window draws it, with the same
+// palette, persistence and upscaler but at a fixed scale. With persistence on they're taken from
+// the window's blender.
+pub struct Capture {
+    dir: PathBuf,
+    // Names the files, from the ROM
+    name: String,
+    look: Look,
+    format: RecordFormat,
+    recording: Option<Recording>,
+}
+
+impl Capture {
+    pub fn new(config: &Config) -> Self {
+        let name = config.rom.as_ref().and_then(|rom| rom.file_stem());
+        Self {
+            dir: config.capture_dir.clone(),
+            name: name.map_or_else(|| "chip8".to_string(), |name| name.to_string_lossy().into_owned()),
+            look: Look { upscaler: config.upscaler, scale: config.capture_scale },
+            format: config.record_format,
+            recording: None,
+        }
+    }
+
+    pub fn recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    // Call after every emulated frame, once the blender has been advanced. Does nothing unless a
+    // recording is running, and stops the recording if it can't be written.
+    pub fn frame(&mut self, chip8: &Chip8, palette: &Palette, blender: Option<&FrameBlender>) {
+        let Some(recording) = &mut self.recording else {
+            return;
+        };
+        if let Err(err) = recording.push(Image::display(chip8, palette, blender)) {
+            log::error!("Stopped recording to {}: {}", recording.path.display(), err);
+            self.recording = None;
+        }
+    }
+
+    // Saves the display to `path` as a PNG
+    pub fn save_screenshot(
+        &self,
+        chip8: &Chip8,
+        palette: &Palette,
+        blender: Option<&FrameBlender>,
+        path: &Path,
+    ) -> io::Result<()> {
+        save_png(path, &self.look.draw(&Image::display(chip8, palette, blender)))
+    }
+
+    // Saves the display to a new PNG in the capture directory, returning its path
+    pub fn screenshot(&self, chip8: &Chip8, palette: &Palette, blender: Option<&FrameBlender>) -> io::Result<PathBuf> {
+        let path = self.file_path("png");
+        self.save_screenshot(chip8, palette, blender, &path)?;
+        Ok(path)
+    }
+
+    // Starts recording from the current display, or stops and finishes writing the recording.
+    // Returns the recording's path either way.
+    pub fn toggle_recording(
+        &mut self,
+        chip8: &Chip8,
+        palette: &Palette,
+        blender: Option<&FrameBlender>,
+    ) -> io::Result<PathBuf> {
+        if let Some(recording) = self.recording.take() {
+            return recording.finish();
+        }
+        // APNGs are PNGs to anything that doesn't know about animation
+        let path = self.file_path(match self.format {
+            RecordFormat::Gif => "gif",
+            RecordFormat::Apng => "png",
+        });
+        let first = Image::display(chip8, palette, blender);
+        let canvas = Canvas { size: chip8.max_resolution(), fill: palette.border };
+        self.recording = Some(Recording::new(path.clone(), self.format, self.look, canvas, first)?);
+        Ok(path)
+    }
+
+    fn file_path(&self, extension: &str) -> PathBuf {
+        let millis = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_millis());
+        self.dir.join(format!("{}-{}.{}", self.name, millis, extension))
+    }
+}
+
+fn save_png(path: &Path, image: &Image) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), image.width as u32, image.height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&image.pixels)?;
+    Ok(writer.finish()?)
+}
+
+enum Output {
+    Gif(gif::Encoder<BufWriter<File>>),
+    // The frame count goes in the header, so frames are kept until the end along with how many
+    // emulated frames each is shown for
+    Apng(Vec<(Image, u32)>),
+}
+
+// The size every frame of a recording is fitted to, in display pixels. It's the largest screen the
+// program can switch to, so switching resolution doesn't shrink the picture.
+#[derive(Debug, Copy, Clone)]
+struct Canvas {
+    size: (usize, usize),
+    // Around smaller screens
+    fill: [u8; 4],
+}
+
+// Records the display at the emulated 60 Hz, writing each distinct frame once for as long as it
+// was shown
+struct Recording {
+    path: PathBuf,
+    look: Look,
+    output: Output,
+    canvas: Canvas,
+    // The latest frame and how many emulated frames it's been shown for
+    pending: (Image, u32),
+    // Emulated frames written so far
+    written: u32,
+}
+
+impl Recording {
+    fn new(path: PathBuf, format: RecordFormat, look: Look, canvas: Canvas, first: Image) -> io::Result<Self> {
+        let output = match format {
+            RecordFormat::Gif => {
+                let (width, height) = (gif_size(canvas.size.0 * look.scale)?, gif_size(canvas.size.1 * look.scale)?);
+                let mut encoder = gif::Encoder::new(BufWriter::new(File::create(&path)?), width, height, &[])
+                    .map_err(gif_error)?;
+                encoder.set_repeat(gif::Repeat::Infinite).map_err(gif_error)?;
+                Output::Gif(encoder)
+            },
+            RecordFormat::Apng => {
+                // Fail now rather than after recording
+                File::create(&path)?;
+                Output::Apng(Vec::new())
+            },
+        };
+        Ok(Self {
+            path,
+            look,
+            output,
+            canvas,
+            pending: (first.fit(canvas.size.0, canvas.size.1, canvas.fill), 1),
+            written: 0,
+        })
+    }
+
+    fn push(&mut self, image: Image) -> io::Result<()> {
+        let image = image.fit(self.canvas.size.0, self.canvas.size.1, self.canvas.fill);
+        if image == self.pending.0 {
+            self.pending.1 += 1;
+            return Ok(());
+        }
+        let (frame, frames) = std::mem::replace(&mut self.pending, (image, 1));
+        self.write(frame, frames)
+    }
+
+    fn write(&mut self, image: Image, frames: u32) -> io::Result<()> {
+        let (start, end) = (self.written, self.written + frames);
+        self.written = end;
+        match &mut self.output {
+            Output::Gif(encoder) => {
+                let delay = gif_delay(start, end);
+                // Shown for less than a GIF frame can be
+                if delay == 0 {
+                    return Ok(());
+                }
+                let mut frame = gif_frame(&self.look.draw(&image));
+                frame.delay = delay;
+                encoder.write_frame(&frame).map_err(gif_error)
+            },
+            Output::Apng(frames_so_far) => {
+                frames_so_far.push((image, frames));
+                Ok(())
+            },
+        }
+    }
+
+    fn finish(mut self) -> io::Result<PathBuf> {
+        let (image, frames) = self.pending.clone();
+        self.write(image, frames)?;
+        match self.output {
+            Output::Gif(encoder) => encoder.into_inner()?.flush()?,
+            Output::Apng(frames) => {
+                let size = self.look.draw(&frames[0].0);
+                let file = BufWriter::new(File::create(&self.path)?);
+                let mut encoder = png::Encoder::new(file, size.width as u32, size.height as u32);
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.set_animated(frames.len() as u32, 0)?;
+                let mut writer = encoder.write_header()?;
+                for (image, frames) in &frames {
+                    writer.set_frame_delay((*frames).min(u16::MAX as u32) as u16, 60)?;
+                    writer.write_image_data(&self.look.draw(image).pixels)?;
+                }
+                writer.finish()?;
+            },
+        }
+        Ok(self.path)
+    }
+}
+
+// Browsers slow down GIF frames shorter than 2 hundredths of a second, so frames are timed in
+// steps of 2 on a 50 Hz clock. Returns the hundredths of a second between emulated frames
+// `start` and `end`, counted from the start so the rounding never drifts.
+fn gif_delay(start: u32, end: u32) -> u16 {
+    let tick = |frame: u32| (frame as u64 * 50 / 60) as u32;
+    ((tick(end) - tick(start)) * 2).min(u16::MAX as u32) as u16
+}
+
+fn gif_frame(image: &Image) -> gif::Frame<'static> {
+    let (width, height) = (image.width as u16, image.height as u16);
+    let mut palette = Vec::new();
+    let mut indices = HashMap::new();
+    let mut pixels = Vec::with_capacity(image.width * image.height);
+    for pixel in image.pixels.chunks_exact(4) {
+        let index = *indices.entry(pixel).or_insert_with(|| {
+            palette.extend_from_slice(&pixel[..3]);
+            palette.len() / 3 - 1
+        });
+        // More colors than a GIF frame can hold, have them quantized
+        if index > u8::MAX as usize {
+            return gif::Frame::from_rgba_speed(width, height, &mut image.pixels.clone(), 10);
+        }
+        pixels.push(index as u8);
+    }
+    gif::Frame::from_palette_pixels(width, height, pixels, palette, None)
+}
+
+fn gif_size(size: usize) -> io::Result<u16> {
+    u16::try_from(size)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too large for a GIF, lower --capture-scale"))
+}
+
+fn gif_error(err: gif::EncodingError) -> io::Error {
+    match err {
+        gif::EncodingError::Io(err) => err,
+        err => io::Error::other(err),
+    }
+}
+
+// Runs the program without a window for `config.screenshot_frames` frames, or until it exits,
+// then saves a screenshot to `config.screenshot`
+pub fn run_headless(config: Config, mut chip8: Chip8) -> io::Result<()> {
+    let mut blender = frame_blender(&config);
+    for _ in 0..config.screenshot_frames {
+        chip8.run_frame(INSTRUCTIONS_PER_FRAME);
+        if let Some(blender) = &mut blender {
+            blender.blend_display(&chip8, &config.palette);
+        }
+        if chip8.exited() {
+            break;
+        }
+    }
+    let path = config.screenshot.as_deref().unwrap_or(Path::new("screenshot.png"));
+    Capture::new(&config).save_screenshot(&chip8, &config.palette, blender.as_ref(), path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const O: [u8; 4] = [0, 0, 0, 0xFF];
+    const X: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+
+    fn image(width: usize, height: usize, pixels: &[[u8; 4]]) -> Image {
+        Image { width, height, pixels: pixels.concat() }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("wgpuchip8-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_resize() {
+        let small = image(2, 1, &[X, O]);
+        assert_eq!(small.resize(4, 2), image(4, 2, &[X, X, O, O, X, X, O, O]));
+        assert_eq!(small.resize(4, 2).resize(2, 1), small);
+    }
+
+    #[test]
+    fn test_fit() {
+        let small = image(2, 1, &[X, O]);
+        assert_eq!(small.fit(4, 2, O), image(4, 2, &[X, X, O, O, X, X, O, O]));
+        // Centered between rows of the fill color
+        assert_eq!(small.fit(2, 3, X), image(2, 3, &[X, X, X, O, X, X]));
+        assert_eq!(image(4, 2, &[X; 8]).fit(2, 1, O), image(2, 1, &[X, X]));
+    }
+
+    #[test]
+    fn test_look() {
+        let small = image(2, 1, &[X, O]);
+        let look = Look { upscaler: Upscaler::None, scale: 3 };
+        assert_eq!(look.draw(&small).width, 6);
+        // Upscalers don't change the size
+        let look = Look { upscaler: Upscaler::Scale3x, scale: 2 };
+        let drawn = look.draw(&small);
+        assert_eq!((drawn.width, drawn.height), (4, 2));
+    }
+
+    #[test]
+    fn test_gif_delay() {
+        // Frames are rounded to 2 hundredths and never drift from 60 Hz
+        assert_eq!(gif_delay(0, 1), 0);
+        assert_eq!(gif_delay(0, 2), 2);
+        assert_eq!(gif_delay(0, 60), 100);
+        let total: u32 = (0..600).map(|frame| gif_delay(frame, frame + 1) as u32).sum();
+        assert_eq!(total, 1000);
+    }
+
+    #[test]
+    fn test_gif_frame() {
+        let frame = gif_frame(&image(3, 1, &[X, O, X]));
+        assert_eq!(&frame.buffer[..], &[0, 1, 0]);
+        assert_eq!(frame.palette, Some(vec![0xFF, 0xFF, 0xFF, 0, 0, 0]));
+    }
+
+    #[test]
+    fn test_screenshot() {
+        let config = Config { capture_scale: 2, ..Config::default() };
+        let chip8 = config.create_chip8(&[]).unwrap();
+        let path = temp_path("screenshot.png");
+        Capture::new(&config).save_screenshot(&chip8, &config.palette, None, &path).unwrap();
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let reader = decoder.read_info().unwrap();
+        assert_eq!((reader.info().width, reader.info().height), (128, 64));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_recording() {
+        let look = Look { upscaler: Upscaler::None, scale: 1 };
+        let path = temp_path("recording.png");
+        let canvas = Canvas { size: (2, 2), fill: O };
+        let mut recording = Recording::new(path.clone(), RecordFormat::Apng, look, canvas, image(1, 1, &[O])).unwrap();
+        // Repeated frames are merged, and lower resolutions are scaled up to the canvas
+        recording.push(image(1, 1, &[O])).unwrap();
+        recording.push(image(1, 1, &[X])).unwrap();
+        recording.push(image(2, 2, &[O, X, X, O])).unwrap();
+        match &recording.output {
+            Output::Apng(frames) => assert_eq!(frames, &[(image(2, 2, &[O; 4]), 2), (image(2, 2, &[X; 4]), 1)]),
+            Output::Gif(_) => unreachable!(),
+        }
+        recording.finish().unwrap();
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let reader = decoder.read_info().unwrap();
+        assert_eq!(reader.info().animation_control.map(|control| control.num_frames), Some(3));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_gif_recording() {
+        let look = Look { upscaler: Upscaler::None, scale: 2 };
+        let path = temp_path("recording.gif");
+        let canvas = Canvas { size: (1, 1), fill: O };
+        let mut recording = Recording::new(path.clone(), RecordFormat::Gif, look, canvas, image(1, 1, &[O])).unwrap();
+        for frame in 0..10 {
+            recording.push(image(1, 1, &[if frame % 2 == 0 { X } else { O }])).unwrap();
+        }
+        recording.finish().unwrap();
+        let mut decoder = gif::DecodeOptions::new().read_info(File::open(&path).unwrap()).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (2, 2));
+        let mut delays = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            delays.push(frame.delay);
+        }
+        // 11 frames at 60 Hz last 18 hundredths of a second
+        assert_eq!(delays.iter().sum::<u16>(), 18);
+        assert!(delays.iter().all(|&delay| delay >= 2));
+        std::fs::remove_file(path).unwrap();
+    }
+}
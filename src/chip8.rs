@@ -1,70 +1,422 @@
+mod color_map;
+mod cycles;
+mod disassembler;
+mod framebuffer;
+mod font;
+mod megachip;
 mod memory;
+mod platform;
 mod registers;
 mod stack;
+mod sys;
 
-use memory::{Memory, MemoryAddress};
-use registers::Registers;
+pub use color_map::ColorMap;
+pub use framebuffer::Framebuffer;
+pub use font::Font;
+pub use megachip::{BlendMode, DigitizedSound, MegaDisplay, MEGACHIP_HEIGHT, MEGACHIP_WIDTH};
+pub use memory::{Memory, MemoryAddress};
+pub use platform::Platform;
+pub use registers::Registers;
+pub use sys::{SysContext, SysHandler};
+use framebuffer::{HIRES_HEIGHT, HIRES_WIDTH, LORES_HEIGHT, LORES_WIDTH, PLANES, TWO_PAGE_HEIGHT};
 use stack::Stack;
+use sys::SysHandlers;
 use crate::util::*;
+use crate::vip::Vip;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex, Condvar};
 
-type Keyboard = Arc<(Mutex<Option<u8>>, Condvar)>;
-type Display = [u8; 8*32];
+pub type Keyboard = Arc<(Mutex<Option<u8>>, Condvar)>;
+
+// SCHIP saves up to 8 registers to the HP48's RPL user flags
+const RPL_FLAGS: usize = 8;
+const AUDIO_PATTERN_BYTES: usize = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Variant {
+    Chip8,
+    SuperChip,
+    XoChip,
+    // The VIP's two page "Hi-res CHIP-8" with a 64x64 display
+    HiresChip8,
+    // CHIP-8X for the VIP with the VP-590 color board and VP-580 second keypad
+    Chip8X,
+    // MegaChip8 with a 256x192 indexed color display and 24 bit addresses
+    MegaChip,
+}
+
+impl Variant {
+    fn has_superchip_instructions(self) -> bool {
+        matches!(self, Variant::SuperChip | Variant::XoChip | Variant::MegaChip)
+    }
+
+    fn has_xochip_instructions(self) -> bool {
+        matches!(self, Variant::XoChip)
+    }
+}
+
+impl FromStr for Variant {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "chip8" => Ok(Variant::Chip8),
+            "schip" | "superchip" => Ok(Variant::SuperChip),
+            "xochip" | "xo-chip" => Ok(Variant::XoChip),
+            "hires" => Ok(Variant::HiresChip8),
+            "chip8x" => Ok(Variant::Chip8X),
+            "megachip" => Ok(Variant::MegaChip),
+            _ => Err(format!("unknown variant: {}", name)),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Chip8 {
-    pub display: Display,
+    pub framebuffer: Framebuffer,
     stack: Stack,
     memory: Memory,
     registers: Registers,
     pc: MemoryAddress,
     i: MemoryAddress,
     pub keyboard: Keyboard,
+    // The CHIP-8X VP-580 keypad
+    pub second_keyboard: Keyboard,
+    // Set by a frontend that's quitting, so an FX0A waiting on the keyboard exits instead of
+    // waiting for a key. Notify the keyboard after setting it.
+    pub quit: Arc<AtomicBool>,
+    color_map: Option<ColorMap>,
+    // Only drawn to while MegaChip mode is switched on with 0011
+    mega_display: MegaDisplay,
+    megachip_mode: bool,
+    digitized_sound: Option<DigitizedSound>,
+    platform: Platform,
+    // Starts out as the platform's variant but can switch to Hi-res CHIP-8 when a program loads
+    variant: Variant,
+    rpl: [u8; RPL_FLAGS],
+    rpl_path: Option<PathBuf>,
+    exited: bool,
+    audio_pattern: [u8; AUDIO_PATTERN_BYTES],
+    pitch: u8,
+    // When set the program runs on an emulated VIP instead
+    vip: Option<Box<Vip>>,
+    sys_handlers: SysHandlers,
+    // Runs as many instructions a frame as the VIP interpreter could, instead of a fixed count
+    vip_timing: bool,
+    // Cycles the last instruction of a VIP timed frame ran over into the next
+    cycle_debt: u32,
+    // Ends the frame after each DXYN, like the VIP waiting for the display interrupt
+    display_wait: bool,
+    // Set when the whole screen needs redrawing, rather than just the framebuffer's dirty rows
+    screen_changed: bool,
 }
 
 impl Chip8 {
     pub fn new() -> Self {
+        Self::with_variant(Variant::Chip8)
+    }
+
+    pub fn with_variant(variant: Variant) -> Self {
+        Self::with_platform(Platform::for_variant(variant))
+    }
+
+    pub fn with_platform(platform: Platform) -> Self {
+        let variant = platform.variant;
+        let mut framebuffer = Framebuffer::with_size(platform.width, platform.height, PLANES);
+        framebuffer.set_sprite_wrapping(variant.has_xochip_instructions());
         Self {
-            display: [0; 8*32],
+            framebuffer,
             stack: Stack::new(),
-            memory: Memory::new(),
+            memory: Memory::with_size(platform.memory_size),
             registers: Registers::new(),
-            pc: MemoryAddress::PROGRAM_START,
+            pc: platform.program_start,
             i: MemoryAddress::ZERO,
             keyboard: Arc::new((Mutex::new(None), Condvar::new())),
-        }.initialize_digit_sprites()
+            second_keyboard: Arc::new((Mutex::new(None), Condvar::new())),
+            quit: Arc::new(AtomicBool::new(false)),
+            color_map: if variant == Variant::Chip8X { Some(ColorMap::new()) } else { None },
+            platform,
+            mega_display: MegaDisplay::new(),
+            megachip_mode: false,
+            digitized_sound: None,
+            variant,
+            rpl: [0; RPL_FLAGS],
+            rpl_path: None,
+            exited: false,
+            audio_pattern: [0; AUDIO_PATTERN_BYTES],
+            pitch: 64,
+            vip: None,
+            sys_handlers: SysHandlers::default(),
+            vip_timing: false,
+            cycle_debt: 0,
+            display_wait: false,
+            screen_changed: true,
+        }.initialize_digit_sprites().initialize_big_digit_sprites()
+    }
+
+    // Runs programs on the original interpreter inside `vip` rather than decoding them here
+    pub fn with_vip(vip: Vip) -> Self {
+        let mut chip8 = Self::new();
+        vip.render(&mut chip8.framebuffer);
+        chip8.vip = Some(Box::new(vip));
+        chip8
     }
 
     fn initialize_digit_sprites(mut self) -> Self {
-        self.memory.write_bytes(MemoryAddress(0), &[0xF0, 0x90, 0x90, 0x90, 0xF0]);  // 0
-        self.memory.write_bytes(MemoryAddress(5), &[0x20, 0x60, 0x20, 0x20, 0x70]);  // 1
-        self.memory.write_bytes(MemoryAddress(10), &[0xF0, 0x10, 0xF0, 0x80, 0xF0]); // 2
-        self.memory.write_bytes(MemoryAddress(15), &[0xF0, 0x10, 0xF0, 0x10, 0xF0]); // 3
-        self.memory.write_bytes(MemoryAddress(20), &[0x90, 0x90, 0xF0, 0x10, 0x10]); // 4
-        self.memory.write_bytes(MemoryAddress(25), &[0xF0, 0x80, 0xF0, 0x10, 0xF0]); // 5
-        self.memory.write_bytes(MemoryAddress(30), &[0xF0, 0x80, 0xF0, 0x90, 0xF0]); // 6
-        self.memory.write_bytes(MemoryAddress(35), &[0xF0, 0x10, 0x20, 0x40, 0x40]); // 7
-        self.memory.write_bytes(MemoryAddress(40), &[0xF0, 0x90, 0xF0, 0x90, 0xF0]); // 8
-        self.memory.write_bytes(MemoryAddress(45), &[0xF0, 0x90, 0xF0, 0x10, 0xF0]); // 9
-        self.memory.write_bytes(MemoryAddress(50), &[0xF0, 0x90, 0xF0, 0x90, 0x90]); // A
-        self.memory.write_bytes(MemoryAddress(55), &[0xE0, 0x90, 0xE0, 0x90, 0xE0]); // B
-        self.memory.write_bytes(MemoryAddress(60), &[0xF0, 0x80, 0x80, 0x80, 0xF0]); // C
-        self.memory.write_bytes(MemoryAddress(65), &[0xE0, 0x90, 0x90, 0x90, 0xE0]); // D
-        self.memory.write_bytes(MemoryAddress(70), &[0xF0, 0x80, 0xF0, 0x80, 0xF0]); // E
-        self.memory.write_bytes(MemoryAddress(75), &[0xF0, 0x80, 0xF0, 0x80, 0x80]); // F
+        self.memory.write_bytes(self.platform.font_start, &self.platform.font.small);
+        self
+    }
+
+    fn initialize_big_digit_sprites(mut self) -> Self {
+        self.memory.write_bytes(self.platform.big_font_start, &self.platform.font.big);
         self
     }
 
+    // Hi-res CHIP-8 programs for the VIP are detected by the 1260 jump they start with
     pub fn load_program(&mut self, program: &[u8]) {
-        self.memory.write_bytes(MemoryAddress::PROGRAM_START, program);
+        if let Some(vip) = &mut self.vip {
+            vip.load_program(program);
+            return;
+        }
+        self.memory.write_bytes(self.platform.program_start, program);
+        let vip_program = self.platform.program_start == MemoryAddress::PROGRAM_START;
+        if self.variant == Variant::Chip8 && vip_program && program.starts_with(&[0x12, 0x60]) {
+            self.variant = Variant::HiresChip8;
+        }
+        if self.variant == Variant::HiresChip8 {
+            self.enter_two_page_mode();
+        }
+    }
+
+    // The 1260 jump lands in a patch to the VIP interpreter at 0x260-0x2BF which switches the
+    // 1861 to a 64x64 display and then starts the CHIP-8 program proper at 0x2C0
+    fn enter_two_page_mode(&mut self) {
+        self.framebuffer.set_resolution(LORES_WIDTH, TWO_PAGE_HEIGHT);
+        self.pc = MemoryAddress::TWO_PAGE_PROGRAM_START;
+    }
+
+    // RPL flags are read from `path` if it exists and written back to it on every FX75
+    pub fn set_rpl_path(&mut self, path: PathBuf) {
+        if let Ok(saved) = std::fs::read(&path) {
+            let n = saved.len().min(RPL_FLAGS);
+            self.rpl[..n].copy_from_slice(&saved[..n]);
+        }
+        self.rpl_path = Some(path);
     }
 
-    fn sys(&mut self, _addr: MemoryAddress) {
-        println!("sys op is unimplemented");
+    // Handlers are asked in the order they were added to handle each 0NNN call
+    pub fn add_sys_handler(&mut self, handler: impl SysHandler + 'static) {
+        self.sys_handlers.0.push(Box::new(handler));
     }
 
+    // Only CHIP-8X has color attributes
+    pub fn color_map(&self) -> Option<&ColorMap> {
+        self.color_map.as_ref()
+    }
+
+    // The MegaChip screen, if MegaChip mode is currently switched on
+    pub fn mega_display(&self) -> Option<&MegaDisplay> {
+        if self.megachip_mode { Some(&self.mega_display) } else { None }
+    }
+
+    // The largest screen the program can switch to. Only 00FF and MegaChip mode change it after
+    // the program is loaded.
+    pub fn max_resolution(&self) -> (usize, usize) {
+        match self.variant() {
+            Variant::MegaChip => (MEGACHIP_WIDTH, MEGACHIP_HEIGHT),
+            variant if variant.has_superchip_instructions() => (HIRES_WIDTH, HIRES_HEIGHT),
+            _ => (self.framebuffer.width(), self.framebuffer.height()),
+        }
+    }
+
+    // The sample most recently started by 060N, until 0700 stops it. Nothing plays it yet, there's
+    // no audio output.
+    pub fn digitized_sound(&self) -> Option<&DigitizedSound> {
+        self.digitized_sound.as_ref()
+    }
+
+    // The rows of the current screen that changed since the last call, or None if nothing was
+    // drawn. Frontends use this to skip or cut down redraws.
+    pub fn take_dirty_rows(&mut self) -> Option<Range<usize>> {
+        let rows = if std::mem::take(&mut self.screen_changed) {
+            let height = if self.megachip_mode { MEGACHIP_HEIGHT } else { self.framebuffer.height() };
+            Some(0..height)
+        } else if self.megachip_mode {
+            None
+        } else {
+            self.framebuffer.dirty_row_range()
+        };
+        self.framebuffer.clear_dirty();
+        rows
+    }
+
+    // The VIP running the program, if there is one. The CHIP-8 state here is unused then.
+    pub fn vip(&self) -> Option<&Vip> {
+        self.vip.as_deref()
+    }
+
+    // True while the beeper sounds, which is while the sound timer runs or, on a VIP, Q is set
+    pub fn sound_on(&self) -> bool {
+        match &self.vip {
+            Some(vip) => vip.sound_on(),
+            None => self.registers[Registers::ST] > 0,
+        }
+    }
+
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    pub fn platform(&self) -> &Platform {
+        &self.platform
+    }
+
+    // The XO-CHIP audio pattern last stored by F002. Nothing plays it yet, there's no audio output.
+    pub fn audio_pattern(&self) -> &[u8; AUDIO_PATTERN_BYTES] {
+        &self.audio_pattern
+    }
+
+    // The rate in Hz at which bits of the XO-CHIP audio pattern are played back
+    pub fn audio_playback_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+
+    pub fn pc(&self) -> MemoryAddress {
+        self.pc
+    }
+
+    pub fn i(&self) -> MemoryAddress {
+        self.i
+    }
+
+    pub fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    pub fn stack(&self) -> &[MemoryAddress] {
+        self.stack.as_slice()
+    }
+
+    pub fn disassemble(&self, addr: MemoryAddress) -> String {
+        match *self.memory.read_bytes(addr, 4) {
+            [0xF0, 0x00, hi, lo] if self.variant.has_xochip_instructions() => format!("LD I, {:02X}{:02X}", hi, lo),
+            [0x01, hi, mid, lo] if self.variant == Variant::MegaChip => format!("LDHI I, {:02X}{:02X}{:02X}", hi, mid, lo),
+            [jj, kk, ..] if self.variant == Variant::MegaChip => disassembler::disassemble_megachip(jj, kk),
+            [jj, kk, ..] => disassembler::disassemble(jj, kk),
+            _ => "??".to_string(),
+        }
+    }
+
+    fn sys(&mut self, addr: MemoryAddress) {
+        let mut context = SysContext {
+            registers: &mut self.registers,
+            i: &mut self.i,
+            memory: &mut self.memory,
+            framebuffer: &mut self.framebuffer,
+        };
+        if !self.sys_handlers.0.iter_mut().any(|handler| handler.call(addr, &mut context)) {
+            log::warn!("No handler for SYS {:03X}", addr.0);
+        }
+    }
+
+    // In MegaChip mode 00E0 shows the frame drawn so far and starts a new one
     fn clear_screen(&mut self) {
-        self.display = [0; 8*32];
+        if self.megachip_mode {
+            self.mega_display.present();
+            self.screen_changed = true;
+        } else {
+            self.framebuffer.clear();
+        }
+    }
+
+    fn scroll_down(&mut self, n: u8) {
+        if self.megachip_mode {
+            self.mega_display.scroll_down(n as usize);
+        } else {
+            self.framebuffer.scroll_down(n as usize);
+        }
+    }
+
+    fn scroll_up(&mut self, n: u8) {
+        if self.megachip_mode {
+            self.mega_display.scroll_up(n as usize);
+        } else {
+            self.framebuffer.scroll_up(n as usize);
+        }
+    }
+
+    fn scroll_right(&mut self) {
+        if self.megachip_mode {
+            self.mega_display.scroll_right(4);
+        } else {
+            self.framebuffer.scroll_right(4);
+        }
+    }
+
+    fn scroll_left(&mut self) {
+        if self.megachip_mode {
+            self.mega_display.scroll_left(4);
+        } else {
+            self.framebuffer.scroll_left(4);
+        }
+    }
+
+    fn set_megachip_mode(&mut self, on: bool) {
+        self.megachip_mode = on;
+        self.mega_display = MegaDisplay::new();
+        self.screen_changed = true;
+    }
+
+    // 01NN NNNN, the low 16 bits of the address are in the two bytes following the instruction
+    fn load_i_24bit(&mut self, high: u8) {
+        if let &[mid, lo] = self.memory.read_bytes(self.pc, 2) {
+            self.i = MemoryAddress(u32::from_be_bytes([0, high, mid, lo]));
+        }
+        self.pc.next_instruction();
+    }
+
+    fn load_palette(&mut self, colors: u8) {
+        let data = self.memory.read_bytes(self.i, colors as usize * 4);
+        self.mega_display.load_palette(data);
+    }
+
+    // Only decodes the sample, it isn't played
+    fn load_digitized_sound(&mut self, mode: u8) {
+        let header = self.memory.read_bytes(self.i, DigitizedSound::HEADER_BYTES);
+        let len = DigitizedSound::HEADER_BYTES + DigitizedSound::length(header);
+        let data = self.memory.read_bytes(self.i, len);
+        if self.digitized_sound.is_none() {
+            log::warn!("Digitized sound isn't supported, the program will be silent");
+        }
+        // 0600 loops the sample, 0601 plays it once
+        self.digitized_sound = Some(DigitizedSound::new(data, mode == 0));
+    }
+
+    fn stop_digitized_sound(&mut self) {
+        self.digitized_sound = None;
+    }
+
+    fn exit(&mut self) {
+        self.exited = true;
+    }
+
+    fn low_resolution(&mut self) {
+        self.framebuffer.set_resolution(LORES_WIDTH, LORES_HEIGHT);
+    }
+
+    fn high_resolution(&mut self) {
+        self.framebuffer.set_resolution(HIRES_WIDTH, HIRES_HEIGHT);
     }
 
     fn ret(&mut self) {
@@ -80,27 +432,41 @@ impl Chip8 {
         self.pc = addr;
     }
 
+    // XO-CHIP's F000 NNNN and MegaChip's 01NN NNNN are four bytes long and skipped in one go
+    fn skip_next_instruction(&mut self) {
+        let next = self.memory.read_bytes(self.pc, 2);
+        let long_load = match self.variant {
+            Variant::XoChip => next == [0xF0, 0x00],
+            Variant::MegaChip => next.first() == Some(&0x01),
+            _ => false,
+        };
+        if long_load {
+            self.pc.next_instruction();
+        }
+        self.pc.next_instruction();
+    }
+
     fn skip_next_eq(&mut self, vx: u8, value: u8) {
         if self.registers.cmp_scalar(vx, value) {
-            self.pc.next_instruction();
+            self.skip_next_instruction();
         }
     }
 
     fn skip_next_ne(&mut self, vx: u8, value: u8) {
         if !self.registers.cmp_scalar(vx, value) {
-            self.pc.next_instruction();
+            self.skip_next_instruction();
         }
     }
 
     fn skip_next_eq_reg(&mut self, vx: u8, vy: u8) {
         if self.registers.cmp_register(vx, vy) {
-            self.pc.next_instruction();
+            self.skip_next_instruction();
         }
     }
 
     fn skip_next_ne_reg(&mut self, vx: u8, vy: u8) {
         if !self.registers.cmp_register(vx, vy) {
-            self.pc.next_instruction();
+            self.skip_next_instruction();
         }
     }
 
@@ -108,10 +474,37 @@ impl Chip8 {
         self.i = addr;
     }
 
+    // F000 NNNN, the address is in the two bytes following the instruction
+    fn load_i_long(&mut self) {
+        if let &[hi, lo] = self.memory.read_bytes(self.pc, 2) {
+            self.i = MemoryAddress(u16::from_be_bytes([hi, lo]) as u32);
+        }
+        self.pc.next_instruction();
+    }
+
+    // 5XY2 saves VX..VY to memory at I without changing I, in reverse order if X > Y
+    fn save_reg_range(&mut self, vx: u8, vy: u8) {
+        for (offset, v) in register_range(vx, vy).enumerate() {
+            self.memory.write_bytes(self.i + offset as u8, &[self.registers[v]]);
+        }
+    }
+
+    // 5XY3 is the reverse of 5XY2
+    fn load_reg_range(&mut self, vx: u8, vy: u8) {
+        for (offset, v) in register_range(vx, vy).enumerate() {
+            self.registers[v] = self.memory.read_bytes(self.i + offset as u8, 1)[0];
+        }
+    }
+
     fn jump_reg0(&mut self, addr: MemoryAddress) {
         self.jump(addr + self.registers[Registers::V0]);
     }
 
+    // SCHIP reads BNNN as BXNN, jumping to XNN + VX
+    fn jump_reg_x(&mut self, vx: u8, addr: MemoryAddress) {
+        self.jump(addr + self.registers[vx]);
+    }
+
     fn rand_and(&mut self, vx: u8, value: u8) {
         self.registers[vx] = value & rand::random::<u8>();
     }
@@ -119,49 +512,89 @@ impl Chip8 {
     fn draw_sprite(&mut self, arg1: u8, arg2: u8, arg3: u8) {
         let x = self.registers[arg1] as usize;
         let y = self.registers[arg2] as usize;
+        if self.megachip_mode {
+            let sprite_data = self.memory.read_bytes(self.i, self.mega_display.sprite_len());
+            let collision = self.mega_display.draw_sprite(x, y, sprite_data);
+            self.registers[Registers::VF] = if collision { 1 } else { 0 };
+            return;
+        }
         let n = low_nibble(arg3) as usize;
-        let sprite_data = self.memory.read_bytes(self.i, n);
-        for (i, source) in sprite_data.iter().enumerate() {
-            let offset = x % 8;
-            if offset == 0 {
-                let target = &mut self.display[8*(y+i) + x/8];
-                let ones_before_blit = source.count_ones() + target.count_ones();
-                *target ^= source;
-                let ones_after_blit = target.count_ones();
-                self.registers[Registers::VF] = if ones_after_blit < ones_before_blit { 1 } else { 0 };
-            } else {
-                let target_a = &mut self.display[8*(y+i) + x/8];
-                let source_a = source >> offset;
-                let mut ones_before_blit = source_a.count_ones() + target_a.count_ones();
-                *target_a ^= source_a;
-                let mut ones_after_blit = target_a.count_ones();
-                let target_b = &mut self.display[8*(y+i) + x/8 + 1];
-                let source_b = source << (8 - offset);
-                ones_before_blit += source_b.count_ones() + target_b.count_ones();
-                *target_b ^= source_b;
-                ones_after_blit += target_b.count_ones();
-                self.registers[Registers::VF] = if ones_after_blit < ones_before_blit { 1 } else { 0 };
-            }
+        // SCHIP draws a 16x16 sprite for DXY0
+        let (num_bytes, bytes_per_row) = if n == 0 && self.variant.has_superchip_instructions() {
+            (32, 2)
+        } else {
+            (n, 1)
+        };
+        // Each selected XO-CHIP plane gets its own copy of the sprite data
+        let num_bytes = num_bytes * self.framebuffer.selected_plane_count();
+        let sprite_data = self.memory.read_bytes(self.i, num_bytes);
+        let collision = self.framebuffer.draw_sprite(x, y, sprite_data, bytes_per_row);
+        self.registers[Registers::VF] = if collision { 1 } else { 0 };
+    }
+
+    fn select_planes(&mut self, mask: u8) {
+        self.framebuffer.select_planes(mask);
+    }
+
+    fn load_audio_pattern(&mut self) {
+        let pattern = self.memory.read_bytes(self.i, AUDIO_PATTERN_BYTES);
+        self.audio_pattern[..pattern.len()].copy_from_slice(pattern);
+    }
+
+    fn load_pitch(&mut self, vx: u8) {
+        self.pitch = self.registers[vx];
+    }
+
+    fn skip_input(&mut self, vx: u8) {
+        if pressed_key(&self.keyboard) == Some(self.registers[vx]) {
+            self.skip_next_instruction();
         }
     }
 
-    fn skip_input(&mut self, arg1: u8) {
-        let (lock, _cvar) = &*self.keyboard;
-        let keyboard = lock.lock().unwrap();
-        if let Some(key_pressed) = *keyboard {
-            if key_pressed == arg1 {
-                self.pc.next_instruction();
-            }
+    fn skip_not_input(&mut self, vx: u8) {
+        if pressed_key(&self.keyboard) != Some(self.registers[vx]) {
+            self.skip_next_instruction();
         }
     }
 
-    fn skip_not_input(&mut self, arg1: u8) {
-        let (lock, _cvar) = &*self.keyboard;
-        let keyboard = lock.lock().unwrap();
-        if let Some(key_pressed) = *keyboard {
-            if key_pressed != arg1 {
-                self.pc.next_instruction();
+    fn skip_second_input(&mut self, vx: u8) {
+        if pressed_key(&self.second_keyboard) == Some(self.registers[vx]) {
+            self.skip_next_instruction();
+        }
+    }
+
+    fn skip_not_second_input(&mut self, vx: u8) {
+        if pressed_key(&self.second_keyboard) != Some(self.registers[vx]) {
+            self.skip_next_instruction();
+        }
+    }
+
+    // CHIP-8X 5XY1 adds the two octal digits packed into each nibble separately
+    fn add_nibbles(&mut self, vx: u8, vy: u8) {
+        let low = ((self.registers[vx] & 0x07) + (self.registers[vy] & 0x07)) & 0x07;
+        let high = (((self.registers[vx] >> 4) & 0x07) + ((self.registers[vy] >> 4) & 0x07)) & 0x07;
+        self.registers[vx] = (high << 4) | low;
+    }
+
+    fn cycle_background(&mut self) {
+        if let Some(color_map) = &mut self.color_map {
+            color_map.cycle_background();
+            self.screen_changed = true;
+        }
+    }
+
+    // BXY0 colors blocks of zones, BXYN colors N rows of a single zone column
+    fn set_foreground(&mut self, vx: u8, vy: u8, n: u8) {
+        let horizontal = self.registers[vx];
+        let vertical = self.registers[(vx + 1) & 0xF];
+        let color = self.registers[vy];
+        if let Some(color_map) = &mut self.color_map {
+            if n == 0 {
+                color_map.fill_blocks(horizontal, vertical, color);
+            } else {
+                color_map.fill_rows(horizontal, vertical, n, color);
             }
+            self.screen_changed = true;
         }
     }
 
@@ -169,6 +602,10 @@ impl Chip8 {
         let (lock, cvar) = &*self.keyboard;
         let mut keyboard = lock.lock().unwrap();
         while (*keyboard).is_none() {
+            if self.quit.load(Ordering::Relaxed) {
+                self.exited = true;
+                return;
+            }
             keyboard = cvar.wait(keyboard).unwrap();
         }
         if let Some(key_pressed) = *keyboard {
@@ -181,7 +618,13 @@ impl Chip8 {
     }
 
     fn load_digit_sprite(&mut self, arg1: u8) {
-        self.i = MemoryAddress((self.registers[arg1] as u16) * 5);
+        // Only the low nibble picks a glyph, like FX30
+        let offset = low_nibble(self.registers[arg1]) as u32 * font::SMALL_GLYPH_BYTES as u32;
+        self.i = MemoryAddress(self.platform.font_start.0 + offset);
+    }
+
+    fn load_big_digit_sprite(&mut self, vx: u8) {
+        self.i = self.platform.big_font_start + low_nibble(self.registers[vx]) * font::BIG_GLYPH_BYTES as u8;
     }
 
     fn load_binary_coded_decimal(&mut self, vx: u8) {
@@ -194,25 +637,146 @@ impl Chip8 {
 
     fn store_regs(&mut self, vy: u8) {
         self.memory.write_bytes(self.i, self.registers.get_slice(Registers::V0, vy));
+        self.increment_i_after_load_store(vy);
     }
 
     fn load_regs(&mut self, vy: u8) {
         let n = vy as usize + 1;
         let data = self.memory.read_bytes(self.i, n);
         self.registers.get_slice_mut(Registers::V0, vy).copy_from_slice(data);
+        self.increment_i_after_load_store(vy);
     }
 
+    // XO-CHIP follows the original interpreter and leaves I pointing past the registers
+    fn increment_i_after_load_store(&mut self, vy: u8) {
+        if self.variant.has_xochip_instructions() {
+            self.i += vy + 1;
+        }
+    }
+
+    fn store_rpl(&mut self, vx: u8) {
+        let n = (vx as usize).min(RPL_FLAGS - 1) + 1;
+        self.rpl[..n].copy_from_slice(self.registers.get_slice(Registers::V0, n as u8 - 1));
+        if let Some(path) = &self.rpl_path {
+            if let Err(err) = std::fs::write(path, self.rpl) {
+                log::warn!("Unable to save RPL flags to {}: {}", path.display(), err);
+            }
+        }
+    }
+
+    fn load_rpl(&mut self, vx: u8) {
+        let n = (vx as usize).min(RPL_FLAGS - 1) + 1;
+        self.registers.get_slice_mut(Registers::V0, n as u8 - 1).copy_from_slice(&self.rpl[..n]);
+    }
+
+    pub fn set_vip_timing(&mut self, on: bool) {
+        self.vip_timing = on;
+        self.cycle_debt = 0;
+    }
+
+    pub fn set_display_wait(&mut self, on: bool) {
+        self.display_wait = on;
+    }
+
+    // Runs one 60 Hz frame worth of instructions and then counts the timers down. With VIP
+    // timing `instructions` is ignored and the frame lasts as many cycles as a VIP frame. A VIP
+    // runs for one 1861 frame instead.
+    pub fn run_frame(&mut self, instructions: u32) {
+        if let Some(vip) = &mut self.vip {
+            vip.set_key(pressed_key(&self.keyboard));
+            vip.run_frame();
+            vip.render(&mut self.framebuffer);
+            return;
+        }
+        if self.vip_timing {
+            self.run_vip_timed_frame();
+        } else {
+            for _ in 0..instructions {
+                let draw = self.next_instruction().is_some_and(|[jj, _]| cycles::is_draw(jj));
+                self.step();
+                if draw && self.display_wait {
+                    break;
+                }
+            }
+        }
+        self.registers.tick_timers();
+    }
+
+    fn run_vip_timed_frame(&mut self) {
+        let mut cycles = self.cycle_debt;
+        while cycles < cycles::VIP_INTERPRETER_CYCLES_PER_FRAME && !self.exited {
+            let Some([jj, kk]) = self.next_instruction() else {
+                // Lets `step` stop the program, like it does without VIP timing
+                self.step();
+                break;
+            };
+            let pc = self.pc;
+            cycles += cycles::vip_cycles(jj, kk, &self.registers);
+            self.step();
+            if self.pc == pc + 4 && matches!(high_nibble(jj), 0x3 | 0x4 | 0x5 | 0x9 | 0xE) {
+                cycles += cycles::SKIP_CYCLES;
+            }
+            if cycles::is_draw(jj) && self.display_wait {
+                cycles = cycles::VIP_INTERPRETER_CYCLES_PER_FRAME;
+            }
+        }
+        self.cycle_debt = cycles.saturating_sub(cycles::VIP_INTERPRETER_CYCLES_PER_FRAME);
+    }
+
+    fn next_instruction(&self) -> Option<[u8; 2]> {
+        self.memory.read_bytes(self.pc, 2).try_into().ok()
+    }
+
+    // Runs one CHIP-8 instruction, or one 1802 instruction when running on a VIP
     pub fn step(&mut self) {
+        if let Some(vip) = &mut self.vip {
+            vip.set_key(pressed_key(&self.keyboard));
+            vip.step();
+            vip.render(&mut self.framebuffer);
+            return;
+        }
+        if self.exited {
+            return;
+        }
         if let &[jj, kk] = self.memory.read_bytes(self.pc, 2) {
             let op = high_nibble(jj);
             let x = low_nibble(jj);
             let y = high_nibble(kk);
             let subop = low_nibble(kk);
-            let nnn: u16 = ((jj as u16) << 8) | (kk as u16);
+            let nnn: u32 = (((jj as u32) << 8) | (kk as u32)) & 0x0FFF;
+            let schip = self.variant.has_superchip_instructions();
+            let xochip = self.variant.has_xochip_instructions();
+            let two_page = self.variant == Variant::HiresChip8;
+            let chip8x = self.variant == Variant::Chip8X;
+            let megachip = self.variant == Variant::MegaChip;
+            // The PC points at the following instruction while this one executes
+            self.pc.next_instruction();
             match op {
                 0x0 => match nnn {
                     0x0E0 => self.clear_screen(),
                     0x0EE => self.ret(),
+                    // The interpreter patch replaces SYS 230 with a clear that covers both pages
+                    0x230 if two_page => self.clear_screen(),
+                    0x2A0 if chip8x => self.cycle_background(),
+                    0x010 if megachip => self.set_megachip_mode(false),
+                    0x011 if megachip => self.set_megachip_mode(true),
+                    0x0B0..=0x0BF if megachip => self.scroll_up(subop),
+                    0x100..=0x1FF if megachip => self.load_i_24bit(kk),
+                    0x200..=0x2FF if megachip => self.load_palette(kk),
+                    0x300..=0x3FF if megachip => self.mega_display.set_sprite_width(kk),
+                    0x400..=0x4FF if megachip => self.mega_display.set_sprite_height(kk),
+                    0x500..=0x5FF if megachip => self.mega_display.alpha = kk,
+                    0x600..=0x60F if megachip => self.load_digitized_sound(subop),
+                    0x700 if megachip => self.stop_digitized_sound(),
+                    0x800..=0x80F if megachip => self.mega_display.blend_mode = BlendMode::from_nibble(subop),
+                    0x900..=0x9FF if megachip => self.mega_display.collision_color = kk,
+                    0x0C0..=0x0CF if schip => self.scroll_down(subop),
+                    0x0D0..=0x0DF if xochip => self.scroll_up(subop),
+                    0x0FB if schip => self.scroll_right(),
+                    0x0FC if schip => self.scroll_left(),
+                    0x0FD if schip => self.exit(),
+                    0x0FE if schip => self.low_resolution(),
+                    0x0FF if schip => self.high_resolution(),
                     _ => self.sys(MemoryAddress(nnn)),
                 },
                 0x1 => self.jump(MemoryAddress(nnn)),
@@ -220,6 +784,9 @@ impl Chip8 {
                 0x3 => self.skip_next_eq(x, kk),
                 0x4 => self.skip_next_ne(x, kk),
                 0x5 if subop == 0x0 => self.skip_next_eq_reg(x, y),
+                0x5 if subop == 0x1 && chip8x => self.add_nibbles(x, y),
+                0x5 if subop == 0x2 && xochip => self.save_reg_range(x, y),
+                0x5 if subop == 0x3 && xochip => self.load_reg_range(x, y),
                 0x6 => self.registers.load_scalar(x, kk),
                 0x7 => self.registers.add_scalar(x, kk),
                 0x8 => match subop {
@@ -236,30 +803,64 @@ impl Chip8 {
                 },
                 0x9 if subop == 0x0 => self.skip_next_ne_reg(x, y),
                 0xA => self.load_i(MemoryAddress(nnn)),
+                0xB if chip8x => self.set_foreground(x, y, subop),
+                0xB if self.variant == Variant::SuperChip => self.jump_reg_x(x, MemoryAddress(nnn)),
                 0xB => self.jump_reg0(MemoryAddress(nnn)),
                 0xC => self.rand_and(x, kk),
                 0xD => self.draw_sprite(x, y, subop),
                 0xE => match kk {
                     0x9E => self.skip_input(x),
                     0xA1 => self.skip_not_input(x),
+                    0xF2 if chip8x => self.skip_second_input(x),
+                    0xF5 if chip8x => self.skip_not_second_input(x),
                     _ => println!("Invalid instruction PC: {:?} I: {:?} STACK: {:?} OP: {:?} X: {:?} Y: {:?} SUBOP: {:?}", self.pc, self.i, self.stack, op, x, y, subop),
                 },
                 0xF => match kk {
+                    0x00 if x == 0 && xochip => self.load_i_long(),
+                    0x01 if xochip => self.select_planes(x),
+                    0x02 if x == 0 && xochip => self.load_audio_pattern(),
                     0x07 => self.registers.load_register(x, Registers::DT),
                     0x0A => self.load_input(x),
                     0x15 => self.registers.load_register(Registers::DT, x),
                     0x18 => self.registers.load_register(Registers::ST, x),
                     0x1E => self.add_i_reg(x),
                     0x29 => self.load_digit_sprite(x),
+                    0x30 if schip => self.load_big_digit_sprite(x),
                     0x33 => self.load_binary_coded_decimal(x),
+                    0x3A if xochip => self.load_pitch(x),
                     0x55 => self.store_regs(x),
                     0x65 => self.load_regs(x),
+                    0x75 if schip => self.store_rpl(x),
+                    0x85 if schip => self.load_rpl(x),
                     _ => println!("Invalid instruction PC: {:?} I: {:?} STACK: {:?} OP: {:?} X: {:?} Y: {:?} SUBOP: {:?}", self.pc, self.i, self.stack, op, x, y, subop),
                 },
                 _ => println!("Invalid instruction PC: {:?} I: {:?} STACK: {:?} OP: {:?} X: {:?} Y: {:?} SUBOP: {:?}", self.pc, self.i, self.stack, op, x, y, subop),
             }
-            self.pc.next_instruction();
-        } else { println!("Unable to read instruction!") }
+        } else {
+            // Only the last byte of memory is left, so there's no way to go on
+            log::error!("Unable to read an instruction at {:03X}, stopping", self.pc.0);
+            self.exited = true;
+        }
+    }
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn pressed_key(keyboard: &Keyboard) -> Option<u8> {
+    let (lock, _cvar) = &**keyboard;
+    let keyboard = lock.lock().unwrap();
+    *keyboard
+}
+
+fn register_range(vx: u8, vy: u8) -> Box<dyn Iterator<Item = u8>> {
+    if vx <= vy {
+        Box::new(vx..=vy)
+    } else {
+        Box::new((vy..=vx).rev())
     }
 }
 
@@ -404,4 +1005,403 @@ mod tests {
         assert_eq!(chip8.registers[Registers::V1], 2);
         assert_eq!(chip8.registers[Registers::V2], 3);
     }
+
+    #[test]
+    fn test_step_jump() {
+        let mut chip8 = Chip8::new();
+        chip8.load_program(&[0x12, 0x04]);
+        chip8.step();
+        assert_eq!(chip8.pc, MemoryAddress(0x204));
+    }
+
+    #[test]
+    fn test_dirty_rows() {
+        let mut chip8 = Chip8::new();
+        assert_eq!(chip8.take_dirty_rows(), Some(0..32));
+        assert_eq!(chip8.take_dirty_rows(), None);
+        chip8.load_program(&[0xA2, 0x02, 0x61, 0x04, 0xD0, 0x12, 0x12, 0x06]);
+        chip8.run_frame(10);
+        assert_eq!(chip8.take_dirty_rows(), Some(4..6));
+        assert_eq!(chip8.take_dirty_rows(), None);
+    }
+
+    #[test]
+    fn test_vip_timing() {
+        let mut chip8 = Chip8::new();
+        chip8.load_program(&[0x60, 0x00].repeat(200));
+        chip8.set_vip_timing(true);
+        chip8.run_frame(10);
+        // Each 6XNN costs 46 cycles, the last one runs over into the next frame
+        let instructions = cycles::VIP_INTERPRETER_CYCLES_PER_FRAME.div_ceil(46);
+        assert_eq!(chip8.pc, MemoryAddress(0x200 + 2 * instructions));
+        assert_eq!(chip8.cycle_debt, instructions * 46 - cycles::VIP_INTERPRETER_CYCLES_PER_FRAME);
+    }
+
+    #[test]
+    fn test_display_wait() {
+        let mut chip8 = Chip8::new();
+        chip8.load_program(&[0xD0, 0x01, 0xD0, 0x01, 0xD0, 0x01]);
+        chip8.set_display_wait(true);
+        chip8.run_frame(10);
+        assert_eq!(chip8.pc, MemoryAddress(0x202));
+        chip8.set_vip_timing(true);
+        chip8.run_frame(10);
+        assert_eq!(chip8.pc, MemoryAddress(0x204));
+        assert_eq!(chip8.cycle_debt, 0);
+    }
+
+    #[test]
+    fn test_truncated_fetch() {
+        for vip_timing in [false, true] {
+            let mut chip8 = Chip8::new();
+            chip8.set_vip_timing(vip_timing);
+            chip8.pc = MemoryAddress(Memory::DEFAULT_SIZE as u32 - 1);
+            chip8.run_frame(10);
+            assert!(chip8.exited());
+            assert_eq!(chip8.pc, MemoryAddress(Memory::DEFAULT_SIZE as u32 - 1));
+        }
+    }
+
+    #[test]
+    fn test_draw_sprite() {
+        let mut chip8 = Chip8::new();
+        chip8.draw_sprite(0, 0, 5);
+        assert!(chip8.framebuffer.pixel(0, 0) && chip8.framebuffer.pixel(3, 4));
+        assert_eq!(chip8.registers[Registers::VF], 0);
+        chip8.draw_sprite(0, 0, 5);
+        assert!(!chip8.framebuffer.pixel(0, 0));
+        assert_eq!(chip8.registers[Registers::VF], 1);
+    }
+
+    #[test]
+    fn test_superchip_resolution_and_large_sprites() {
+        let mut chip8 = Chip8::with_variant(Variant::SuperChip);
+        chip8.load_program(&[0x00, 0xFF, 0xD0, 0x00]);
+        let mut sprite = [0; 32];
+        sprite[1] = 0x01;
+        sprite[30] = 0x80;
+        chip8.memory.write_bytes(MemoryAddress(0x300), &sprite);
+        chip8.load_i(MemoryAddress(0x300));
+        chip8.step();
+        assert_eq!(chip8.framebuffer.width(), 128);
+        chip8.step();
+        assert!(chip8.framebuffer.pixel(15, 0) && chip8.framebuffer.pixel(0, 15));
+        assert!(!chip8.framebuffer.pixel(0, 0));
+    }
+
+    #[test]
+    fn test_superchip_instructions_are_sys_calls_on_chip8() {
+        let mut chip8 = Chip8::new();
+        chip8.load_program(&[0x00, 0xFF, 0x00, 0xFD]);
+        chip8.step();
+        chip8.step();
+        assert_eq!(chip8.framebuffer.width(), 64);
+        assert!(!chip8.exited());
+    }
+
+    #[test]
+    fn test_exit() {
+        let mut chip8 = Chip8::with_variant(Variant::SuperChip);
+        chip8.load_program(&[0x00, 0xFD]);
+        chip8.step();
+        assert!(chip8.exited());
+        chip8.step();
+        assert_eq!(chip8.pc, MemoryAddress(0x202));
+    }
+
+    #[test]
+    fn test_jump_reg_x() {
+        let mut chip8 = Chip8::with_variant(Variant::SuperChip);
+        chip8.registers[Registers::V2] = 0x10;
+        chip8.load_program(&[0xB2, 0x30]);
+        chip8.step();
+        assert_eq!(chip8.pc, MemoryAddress(0x240));
+    }
+
+    #[test]
+    fn test_load_big_digit_sprite() {
+        let mut chip8 = Chip8::with_variant(Variant::SuperChip);
+        chip8.registers[Registers::V0] = 3;
+        chip8.load_big_digit_sprite(0);
+        assert_eq!(chip8.i, chip8.platform().big_font_start + 30);
+        assert_eq!(chip8.memory.read_bytes(chip8.i, 2), &[0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn test_store_load_rpl() {
+        let path = std::env::temp_dir().join(format!("wgpuchip8-rpl-{}", std::process::id()));
+        let mut chip8 = Chip8::with_variant(Variant::SuperChip);
+        chip8.set_rpl_path(path.clone());
+        chip8.registers[Registers::V0] = 1;
+        chip8.registers[Registers::V1] = 2;
+        chip8.store_rpl(1);
+
+        let mut chip8 = Chip8::with_variant(Variant::SuperChip);
+        chip8.set_rpl_path(path.clone());
+        chip8.load_rpl(0xF);
+        assert_eq!(chip8.registers.get_slice(Registers::V0, Registers::V2), &[1, 2, 0]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_xochip_long_load_and_skip() {
+        let mut chip8 = Chip8::with_variant(Variant::XoChip);
+        chip8.load_program(&[0x30, 0x00, 0xF0, 0x00, 0xAB, 0xCD, 0xF0, 0x00, 0x12, 0x34]);
+        chip8.step();
+        assert_eq!(chip8.pc, MemoryAddress(0x206));
+        chip8.step();
+        assert_eq!(chip8.i, MemoryAddress(0x1234));
+        assert_eq!(chip8.pc, MemoryAddress(0x20A));
+    }
+
+    #[test]
+    fn test_save_load_reg_range() {
+        let mut chip8 = Chip8::with_variant(Variant::XoChip);
+        chip8.i = MemoryAddress(0x300);
+        chip8.registers[Registers::V2] = 2;
+        chip8.registers[Registers::V3] = 3;
+        chip8.save_reg_range(3, 2);
+        assert_eq!(chip8.memory.read_bytes(chip8.i, 2), &[3, 2]);
+        chip8.load_reg_range(4, 5);
+        assert_eq!(chip8.registers.get_slice(Registers::V4, Registers::V5), &[3, 2]);
+        assert_eq!(chip8.i, MemoryAddress(0x300));
+    }
+
+    #[test]
+    fn test_xochip_store_regs_increments_i() {
+        let mut chip8 = Chip8::with_variant(Variant::XoChip);
+        chip8.store_regs(2);
+        assert_eq!(chip8.i, MemoryAddress(3));
+    }
+
+    #[test]
+    fn test_xochip_draws_to_selected_planes() {
+        let mut chip8 = Chip8::with_variant(Variant::XoChip);
+        chip8.load_program(&[0xF3, 0x01, 0xD0, 0x01]);
+        chip8.memory.write_bytes(MemoryAddress(0x300), &[0x80, 0x40]);
+        chip8.load_i(MemoryAddress(0x300));
+        chip8.step();
+        chip8.step();
+        assert_eq!(chip8.framebuffer.pixel_planes(0, 0), 0b01);
+        assert_eq!(chip8.framebuffer.pixel_planes(1, 0), 0b10);
+    }
+
+    #[test]
+    fn test_audio_pattern_and_pitch() {
+        let mut chip8 = Chip8::with_variant(Variant::XoChip);
+        chip8.memory.write_bytes(MemoryAddress(0x300), &[0xAA; 16]);
+        chip8.load_i(MemoryAddress(0x300));
+        chip8.load_audio_pattern();
+        assert_eq!(chip8.audio_pattern(), &[0xAA; 16]);
+        assert_eq!(chip8.audio_playback_rate(), 4000.0);
+        chip8.registers[Registers::V0] = 112;
+        chip8.load_pitch(0);
+        assert_eq!(chip8.audio_playback_rate(), 8000.0);
+    }
+
+    #[test]
+    fn test_quit_while_waiting_for_a_key() {
+        let mut chip8 = Chip8::new();
+        chip8.load_program(&[0x60, 0x05, 0xF0, 0x0A]);
+        chip8.step();
+        chip8.quit.store(true, Ordering::Relaxed);
+        chip8.step();
+        assert!(chip8.exited());
+        assert_eq!(chip8.registers[Registers::V0], 5);
+    }
+
+    #[test]
+    fn test_sound_on() {
+        let mut chip8 = Chip8::new();
+        assert!(!chip8.sound_on());
+        chip8.registers[Registers::ST] = 1;
+        assert!(chip8.sound_on());
+        chip8.run_frame(0);
+        assert!(!chip8.sound_on());
+
+        // SEQ turns the VIP's beeper on
+        let mut chip8 = Chip8::with_vip(Vip::new(&[0x7B], &[]));
+        assert!(!chip8.sound_on());
+        chip8.step();
+        assert!(chip8.sound_on());
+    }
+
+    #[test]
+    fn test_eti_660_display() {
+        let mut chip8 = Chip8::with_platform(Platform::eti_660());
+        chip8.load_program(&[0x00, 0xE0]);
+        assert_eq!((chip8.framebuffer.width(), chip8.framebuffer.height()), (64, 48));
+        assert_eq!(chip8.max_resolution(), (64, 48));
+    }
+
+    #[test]
+    fn test_detects_two_page_mode() {
+        let mut chip8 = Chip8::new();
+        chip8.load_program(&[0x12, 0x60]);
+        assert_eq!(chip8.variant(), Variant::HiresChip8);
+        assert_eq!(chip8.pc, MemoryAddress(0x2C0));
+        assert_eq!((chip8.framebuffer.width(), chip8.framebuffer.height()), (64, 64));
+
+        let mut chip8 = Chip8::with_variant(Variant::SuperChip);
+        chip8.load_program(&[0x12, 0x60]);
+        assert_eq!(chip8.variant(), Variant::SuperChip);
+        assert_eq!(chip8.pc, MemoryAddress::PROGRAM_START);
+    }
+
+    #[test]
+    fn test_two_page_draw_and_clear() {
+        let mut chip8 = Chip8::with_variant(Variant::HiresChip8);
+        let mut program = vec![0; 0xC4];
+        program[0xC0..].copy_from_slice(&[0xD0, 0x11, 0x02, 0x30]);
+        chip8.load_program(&program);
+        chip8.registers[Registers::V1] = 60;
+        chip8.step();
+        assert!(chip8.framebuffer.pixel(0, 60) && chip8.framebuffer.pixel(3, 60));
+        chip8.step();
+        assert!(!chip8.framebuffer.pixel(0, 60));
+    }
+
+    #[test]
+    fn test_chip8x_loads_at_0x300() {
+        let mut chip8 = Chip8::with_variant(Variant::Chip8X);
+        chip8.load_program(&[0x02, 0xA0]);
+        assert_eq!(chip8.pc, MemoryAddress(0x300));
+        let background = chip8.color_map().unwrap().background();
+        chip8.step();
+        assert_ne!(chip8.color_map().unwrap().background(), background);
+    }
+
+    #[test]
+    fn test_chip8x_set_foreground() {
+        let mut chip8 = Chip8::with_variant(Variant::Chip8X);
+        chip8.registers[Registers::V2] = 0x00;
+        chip8.registers[Registers::V3] = 0x00;
+        chip8.registers[Registers::V4] = 7;
+        let before = chip8.color_map().unwrap().foreground(0, 0);
+        chip8.set_foreground(2, 4, 0);
+        let after = chip8.color_map().unwrap().foreground(0, 3);
+        assert_ne!(before, after);
+        assert_eq!(chip8.color_map().unwrap().foreground(8, 0), before);
+    }
+
+    #[test]
+    fn test_add_nibbles() {
+        let mut chip8 = Chip8::with_variant(Variant::Chip8X);
+        chip8.registers[Registers::V0] = 0x36;
+        chip8.registers[Registers::V1] = 0x63;
+        chip8.add_nibbles(0, 1);
+        assert_eq!(chip8.registers[Registers::V0], 0x11);
+    }
+
+    #[test]
+    fn test_skip_second_input() {
+        let mut chip8 = Chip8::with_variant(Variant::Chip8X);
+        chip8.registers[Registers::V0] = 0xA;
+        *chip8.second_keyboard.0.lock().unwrap() = Some(0xA);
+        let pc = chip8.pc;
+        chip8.skip_second_input(0);
+        assert_eq!(chip8.pc, pc + 2);
+        chip8.skip_input(0);
+        assert_eq!(chip8.pc, pc + 2);
+    }
+
+    #[test]
+    fn test_platform_layout() {
+        let mut chip8 = Chip8::with_platform(Platform::eti_660());
+        chip8.load_program(&[0x12, 0x60]);
+        assert_eq!(chip8.variant(), Variant::Chip8);
+        assert_eq!(chip8.pc, MemoryAddress(0x600));
+
+        let mut chip8 = Chip8::with_platform(Platform::schip());
+        chip8.registers[Registers::V0] = 1;
+        chip8.load_digit_sprite(0);
+        assert_eq!(chip8.i, MemoryAddress(0x55));
+        assert_eq!(chip8.memory.read_bytes(chip8.i, 5), &[0x20, 0x60, 0x20, 0x20, 0x70]);
+        // The high nibble doesn't move I past the font
+        chip8.registers[Registers::V0] = 0x1A;
+        chip8.load_digit_sprite(0);
+        assert_eq!(chip8.i, MemoryAddress(0x50 + 0xA * 5));
+    }
+
+    #[test]
+    fn test_max_resolution() {
+        assert_eq!(Chip8::new().max_resolution(), (64, 32));
+        assert_eq!(Chip8::with_variant(Variant::SuperChip).max_resolution(), (128, 64));
+        assert_eq!(Chip8::with_variant(Variant::MegaChip).max_resolution(), (256, 192));
+        let mut chip8 = Chip8::new();
+        chip8.load_program(&[0x12, 0x60]);
+        assert_eq!(chip8.max_resolution(), (64, 64));
+    }
+
+    #[test]
+    fn test_selected_font() {
+        let platform = Platform { font: Font::vip(), ..Platform::vip() };
+        let mut chip8 = Chip8::with_platform(platform);
+        chip8.registers[Registers::V0] = 0xB;
+        chip8.load_digit_sprite(0);
+        assert_eq!(chip8.memory.read_bytes(chip8.i, 5), &[0xF0, 0x50, 0x70, 0x50, 0xF0]);
+    }
+
+    #[test]
+    fn test_sys_handlers() {
+        let mut chip8 = Chip8::new();
+        chip8.add_sys_handler(|addr: MemoryAddress, context: &mut SysContext| {
+            addr == MemoryAddress(0x123) && {
+                context.registers[Registers::V0] = 0x42;
+                *context.i = MemoryAddress(0x300);
+                true
+            }
+        });
+        chip8.add_sys_handler(|_: MemoryAddress, context: &mut SysContext| {
+            context.registers[Registers::V1] += 1;
+            true
+        });
+        chip8.load_program(&[0x01, 0x23, 0x04, 0x56]);
+        chip8.step();
+        assert_eq!((chip8.registers[Registers::V0], chip8.registers[Registers::V1]), (0x42, 0));
+        assert_eq!(chip8.i, MemoryAddress(0x300));
+        chip8.step();
+        assert_eq!(chip8.registers[Registers::V1], 1);
+    }
+
+    #[test]
+    fn test_megachip_long_load_and_skip() {
+        let mut chip8 = Chip8::with_variant(Variant::MegaChip);
+        chip8.load_program(&[0x30, 0x00, 0x01, 0x12, 0x34, 0x56, 0x01, 0xAB, 0xCD, 0xEF]);
+        chip8.step();
+        assert_eq!(chip8.pc, MemoryAddress(0x206));
+        chip8.step();
+        assert_eq!(chip8.i, MemoryAddress(0xABCDEF));
+        assert_eq!(chip8.pc, MemoryAddress(0x20A));
+    }
+
+    #[test]
+    fn test_megachip_draw_and_present() {
+        let mut chip8 = Chip8::with_variant(Variant::MegaChip);
+        chip8.load_program(&[0xA3, 0x00, 0x00, 0x11, 0x02, 0x01, 0x03, 0x02, 0x04, 0x01, 0xD0, 0x00, 0x00, 0xE0]);
+        chip8.memory.write_bytes(MemoryAddress(0x300), &[0xFF, 0x12, 0x34, 0x56]);
+        for _ in 0..5 {
+            chip8.step();
+        }
+        chip8.memory.write_bytes(MemoryAddress(0x300), &[1, 1]);
+        chip8.step();
+        assert_eq!(chip8.registers[Registers::VF], 0);
+        chip8.step();
+        let rgba = chip8.mega_display().unwrap().to_rgba();
+        assert_eq!(rgba[0..8], [0x12, 0x34, 0x56, 0xFF, 0x12, 0x34, 0x56, 0xFF]);
+        assert_eq!(rgba[8..12], [0, 0, 0, 0xFF]);
+    }
+
+    #[test]
+    fn test_megachip_digitized_sound() {
+        let mut chip8 = Chip8::with_variant(Variant::MegaChip);
+        chip8.memory.write_bytes(MemoryAddress(0x300), &[0x1F, 0x40, 0x00, 0x00, 0x01, 0x00, 0x80]);
+        chip8.load_program(&[0xA3, 0x00, 0x06, 0x01, 0x07, 0x00]);
+        chip8.step();
+        chip8.step();
+        let sound = chip8.digitized_sound().unwrap();
+        assert_eq!((sound.sample_rate, sound.samples.as_slice(), sound.looping), (8000, &[0x80][..], false));
+        chip8.step();
+        assert!(chip8.digitized_sound().is_none());
+    }
 }
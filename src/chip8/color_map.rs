@@ -0,0 +1,116 @@
+use super::framebuffer::{LORES_HEIGHT, LORES_WIDTH};
+
+// CHIP-8X colors come from the VP-590 color board in zones 8 pixels wide and 1 pixel tall
+pub const ZONE_WIDTH: usize = 8;
+const COLUMNS: usize = LORES_WIDTH / ZONE_WIDTH;
+const ROWS: usize = LORES_HEIGHT;
+// BXY0 addresses zones in blocks of four rows
+const BLOCK_HEIGHT: usize = 4;
+
+const FOREGROUND_COLORS: [[u8; 4]; 8] = [
+    [0x00, 0x00, 0x00, 0xFF], // black
+    [0xFF, 0x00, 0x00, 0xFF], // red
+    [0x00, 0x00, 0xFF, 0xFF], // blue
+    [0xFF, 0x00, 0xFF, 0xFF], // violet
+    [0x00, 0xFF, 0x00, 0xFF], // green
+    [0xFF, 0xFF, 0x00, 0xFF], // yellow
+    [0x00, 0xFF, 0xFF, 0xFF], // aqua
+    [0xFF, 0xFF, 0xFF, 0xFF], // white
+];
+
+// 02A0 steps through these in order
+const BACKGROUND_COLORS: [[u8; 4]; 4] = [
+    [0x00, 0x00, 0x80, 0xFF], // blue
+    [0x00, 0x00, 0x00, 0xFF], // black
+    [0x00, 0x80, 0x00, 0xFF], // green
+    [0x80, 0x00, 0x00, 0xFF], // red
+];
+
+const DEFAULT_FOREGROUND: u8 = 1;
+
+// The color attributes for a monochrome CHIP-8X display
+#[derive(Debug, Clone)]
+pub struct ColorMap {
+    zones: [u8; COLUMNS * ROWS],
+    background: usize,
+}
+
+impl ColorMap {
+    pub fn new() -> Self {
+        Self {
+            zones: [DEFAULT_FOREGROUND; COLUMNS * ROWS],
+            background: 0,
+        }
+    }
+
+    pub fn foreground(&self, x: usize, y: usize) -> [u8; 4] {
+        let zone = self.zones[(y % ROWS) * COLUMNS + (x / ZONE_WIDTH) % COLUMNS];
+        FOREGROUND_COLORS[zone as usize]
+    }
+
+    pub fn background(&self) -> [u8; 4] {
+        BACKGROUND_COLORS[self.background]
+    }
+
+    pub fn cycle_background(&mut self) {
+        self.background = (self.background + 1) % BACKGROUND_COLORS.len();
+    }
+
+    // BXY0, the low nibbles of `horizontal` and `vertical` give the first zone column and block
+    // and the high nibbles how many more columns and blocks to cover
+    pub fn fill_blocks(&mut self, horizontal: u8, vertical: u8, color: u8) {
+        let (column, columns) = ((horizontal & 0xF) as usize, (horizontal >> 4) as usize + 1);
+        let (block, blocks) = ((vertical & 0xF) as usize, (vertical >> 4) as usize + 1);
+        for row in block * BLOCK_HEIGHT..(block + blocks) * BLOCK_HEIGHT {
+            for column in column..column + columns {
+                self.set_zone(column, row, color);
+            }
+        }
+    }
+
+    // BXYN, colors `height` rows of the zone containing pixel (x, y)
+    pub fn fill_rows(&mut self, x: u8, y: u8, height: u8, color: u8) {
+        let column = x as usize / ZONE_WIDTH;
+        for row in y as usize..y as usize + height as usize {
+            self.set_zone(column, row, color);
+        }
+    }
+
+    fn set_zone(&mut self, column: usize, row: usize, color: u8) {
+        if column < COLUMNS && row < ROWS {
+            self.zones[row * COLUMNS + column] = color & 0x7;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fill_blocks() {
+        let mut colors = ColorMap::new();
+        colors.fill_blocks(0x11, 0x00, 4);
+        assert_eq!(colors.foreground(8, 0), FOREGROUND_COLORS[4]);
+        assert_eq!(colors.foreground(23, 3), FOREGROUND_COLORS[4]);
+        assert_eq!(colors.foreground(23, 4), FOREGROUND_COLORS[1]);
+        assert_eq!(colors.foreground(24, 0), FOREGROUND_COLORS[1]);
+    }
+
+    #[test]
+    fn test_fill_rows() {
+        let mut colors = ColorMap::new();
+        colors.fill_rows(63, 30, 5, 7);
+        assert_eq!(colors.foreground(56, 31), FOREGROUND_COLORS[7]);
+        assert_eq!(colors.foreground(56, 29), FOREGROUND_COLORS[1]);
+    }
+
+    #[test]
+    fn test_cycle_background() {
+        let mut colors = ColorMap::new();
+        for _ in 0..BACKGROUND_COLORS.len() {
+            colors.cycle_background();
+        }
+        assert_eq!(colors.background(), BACKGROUND_COLORS[0]);
+    }
+}
@@ -0,0 +1,73 @@
+use super::Registers;
+use crate::util::*;
+
+// The VIP's 1802 runs at 1.76 MHz with 8 clocks per machine cycle, about 3668 machine cycles a
+// frame. The 1861 takes 1024 of those for DMA and its interrupt routine takes about 110 more.
+const VIP_CYCLES_PER_FRAME: u32 = 3668;
+const DISPLAY_CYCLES_PER_FRAME: u32 = 1024 + 110;
+pub const VIP_INTERPRETER_CYCLES_PER_FRAME: u32 = VIP_CYCLES_PER_FRAME - DISPLAY_CYCLES_PER_FRAME;
+
+// Every instruction is fetched and dispatched through a jump table first
+const FETCH_CYCLES: u32 = 40;
+// Extra cost of skipping the next instruction
+pub const SKIP_CYCLES: u32 = 4;
+
+// Machine cycles the VIP interpreter spends on `jj kk`, approximated from its code. Costs that
+// depend on data are worked out from `registers` before the instruction runs. Anything the VIP
+// interpreter doesn't have is charged the fetch alone.
+pub fn vip_cycles(jj: u8, kk: u8, registers: &Registers) -> u32 {
+    let x = low_nibble(jj);
+    let n = low_nibble(kk) as u32;
+    FETCH_CYCLES + match (high_nibble(jj), kk) {
+        (0x0, 0xE0) if x == 0 => 3078,
+        (0x0, 0xEE) if x == 0 => 10,
+        (0x0, _) => 0,
+        (0x1, _) => 12,
+        (0x2, _) => 26,
+        (0x3, _) | (0x4, _) => 10,
+        (0x5, _) | (0x9, _) => 14,
+        (0x6, _) => 6,
+        (0x7, _) => 10,
+        (0x8, _) if n == 0 => 12,
+        (0x8, _) => 44,
+        (0xA, _) => 12,
+        (0xB, _) => 22,
+        (0xC, _) => 36,
+        // Sprites not aligned to a byte are shifted across two bytes
+        (0xD, _) => 26 + n * if registers[x].is_multiple_of(8) { 46 } else { 66 },
+        (0xE, _) => 14,
+        (0xF, 0x07) | (0xF, 0x15) | (0xF, 0x18) => 10,
+        (0xF, 0x0A) => 19,
+        (0xF, 0x1E) | (0xF, 0x29) => 16,
+        // Each digit is found by repeated subtraction
+        (0xF, 0x33) => {
+            let value = registers[x] as u32;
+            80 + 16 * (value / 100 + value / 10 % 10 + value % 10)
+        },
+        (0xF, 0x55) | (0xF, 0x65) => 14 + 14 * (x as u32 + 1),
+        _ => 0,
+    }
+}
+
+// DXYN, which waits for the display interrupt when the display wait quirk is on
+pub fn is_draw(jj: u8) -> bool {
+    high_nibble(jj) == 0xD
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vip_cycles() {
+        let mut registers = Registers::new();
+        assert_eq!(vip_cycles(0x60, 0x12, &registers), 46);
+        assert_eq!(vip_cycles(0x00, 0xE0, &registers), 3118);
+        assert_eq!(vip_cycles(0xD0, 0x12, &registers), 40 + 26 + 2 * 46);
+        registers[0] = 3;
+        assert_eq!(vip_cycles(0xD0, 0x12, &registers), 40 + 26 + 2 * 66);
+        registers[0] = 123;
+        assert_eq!(vip_cycles(0xF0, 0x33, &registers), 40 + 80 + 16 * 6);
+        assert_eq!(vip_cycles(0xF3, 0x55, &registers), 40 + 14 + 14 * 4);
+    }
+}
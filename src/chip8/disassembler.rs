@@ -0,0 +1,124 @@
+use crate::util::*;
+
+pub fn disassemble(jj: u8, kk: u8) -> String {
+    let op = high_nibble(jj);
+    let x = low_nibble(jj);
+    let y = high_nibble(kk);
+    let subop = low_nibble(kk);
+    let nnn: u16 = (((jj as u16) << 8) | (kk as u16)) & 0x0FFF;
+    match op {
+        0x0 => match nnn {
+            0x0E0 => "CLS".to_string(),
+            0x0EE => "RET".to_string(),
+            0x0C0..=0x0CF => format!("SCD {:X}", subop),
+            0x0D0..=0x0DF => format!("SCU {:X}", subop),
+            0x0FB => "SCR".to_string(),
+            0x0FC => "SCL".to_string(),
+            0x0FD => "EXIT".to_string(),
+            0x0FE => "LOW".to_string(),
+            0x0FF => "HIGH".to_string(),
+            _ => format!("SYS {:03X}", nnn),
+        },
+        0x1 => format!("JP {:03X}", nnn),
+        0x2 => format!("CALL {:03X}", nnn),
+        0x3 => format!("SE V{:X}, {:02X}", x, kk),
+        0x4 => format!("SNE V{:X}, {:02X}", x, kk),
+        0x5 if subop == 0x0 => format!("SE V{:X}, V{:X}", x, y),
+        0x5 if subop == 0x2 => format!("SAVE V{:X} - V{:X}", x, y),
+        0x5 if subop == 0x3 => format!("LOAD V{:X} - V{:X}", x, y),
+        0x6 => format!("LD V{:X}, {:02X}", x, kk),
+        0x7 => format!("ADD V{:X}, {:02X}", x, kk),
+        0x8 => match subop {
+            0x0 => format!("LD V{:X}, V{:X}", x, y),
+            0x1 => format!("OR V{:X}, V{:X}", x, y),
+            0x2 => format!("AND V{:X}, V{:X}", x, y),
+            0x3 => format!("XOR V{:X}, V{:X}", x, y),
+            0x4 => format!("ADD V{:X}, V{:X}", x, y),
+            0x5 => format!("SUB V{:X}, V{:X}", x, y),
+            0x6 => format!("SHR V{:X}", x),
+            0x7 => format!("SUBN V{:X}, V{:X}", x, y),
+            0xE => format!("SHL V{:X}", x),
+            _ => format!("DW {:02X}{:02X}", jj, kk),
+        },
+        0x9 if subop == 0x0 => format!("SNE V{:X}, V{:X}", x, y),
+        0xA => format!("LD I, {:03X}", nnn),
+        0xB => format!("JP V0, {:03X}", nnn),
+        0xC => format!("RND V{:X}, {:02X}", x, kk),
+        0xD => format!("DRW V{:X}, V{:X}, {:X}", x, y, subop),
+        0xE => match kk {
+            0x9E => format!("SKP V{:X}", x),
+            0xA1 => format!("SKNP V{:X}", x),
+            _ => format!("DW {:02X}{:02X}", jj, kk),
+        },
+        0xF => match kk {
+            0x00 if x == 0 => "LD I, LONG".to_string(),
+            0x01 => format!("PLANE {:X}", x),
+            0x02 if x == 0 => "AUDIO".to_string(),
+            0x07 => format!("LD V{:X}, DT", x),
+            0x0A => format!("LD V{:X}, K", x),
+            0x15 => format!("LD DT, V{:X}", x),
+            0x18 => format!("LD ST, V{:X}", x),
+            0x1E => format!("ADD I, V{:X}", x),
+            0x29 => format!("LD F, V{:X}", x),
+            0x30 => format!("LD HF, V{:X}", x),
+            0x33 => format!("LD B, V{:X}", x),
+            0x3A => format!("PITCH V{:X}", x),
+            0x55 => format!("LD [I], V{:X}", x),
+            0x65 => format!("LD V{:X}, [I]", x),
+            0x75 => format!("LD R, V{:X}", x),
+            0x85 => format!("LD V{:X}, R", x),
+            _ => format!("DW {:02X}{:02X}", jj, kk),
+        },
+        _ => format!("DW {:02X}{:02X}", jj, kk),
+    }
+}
+
+// MegaChip reuses the 0NNN space, these are only decoded when it is the running variant
+pub fn disassemble_megachip(jj: u8, kk: u8) -> String {
+    match (jj, kk) {
+        (0x00, 0x10) => "MEGAOFF".to_string(),
+        (0x00, 0x11) => "MEGAON".to_string(),
+        (0x00, 0xB0..=0xBF) => format!("SCRU {:X}", low_nibble(kk)),
+        (0x02, _) => format!("LDPAL {:02X}", kk),
+        (0x03, _) => format!("SPRW {:02X}", kk),
+        (0x04, _) => format!("SPRH {:02X}", kk),
+        (0x05, _) => format!("ALPHA {:02X}", kk),
+        (0x06, 0x00..=0x0F) => format!("DIGISND {:X}", kk),
+        (0x07, 0x00) => "STOPSND".to_string(),
+        (0x08, 0x00..=0x0F) => format!("BMODE {:X}", kk),
+        (0x09, _) => format!("CCOL {:02X}", kk),
+        _ => disassemble(jj, kk),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disassemble_known_ops() {
+        assert_eq!(disassemble(0x00, 0xE0), "CLS");
+        assert_eq!(disassemble(0x12, 0x34), "JP 234");
+        assert_eq!(disassemble(0x8A, 0xB4), "ADD VA, VB");
+        assert_eq!(disassemble(0xD0, 0x15), "DRW V0, V1, 5");
+        assert_eq!(disassemble(0xF3, 0x65), "LD V3, [I]");
+        assert_eq!(disassemble(0x00, 0xC4), "SCD 4");
+        assert_eq!(disassemble(0xF7, 0x75), "LD R, V7");
+        assert_eq!(disassemble(0x51, 0x22), "SAVE V1 - V2");
+        assert_eq!(disassemble(0xF3, 0x01), "PLANE 3");
+    }
+
+    #[test]
+    fn test_disassemble_unknown_ops() {
+        assert_eq!(disassemble(0x51, 0x24), "DW 5124");
+        assert_eq!(disassemble(0xE0, 0x00), "DW E000");
+    }
+
+    #[test]
+    fn test_disassemble_megachip_ops() {
+        assert_eq!(disassemble_megachip(0x00, 0x11), "MEGAON");
+        assert_eq!(disassemble_megachip(0x02, 0x10), "LDPAL 10");
+        assert_eq!(disassemble_megachip(0x08, 0x04), "BMODE 4");
+        assert_eq!(disassemble_megachip(0x00, 0xE0), "CLS");
+    }
+}
@@ -0,0 +1,345 @@
+use std::ops::Range;
+
+pub const LORES_WIDTH: usize = 64;
+pub const LORES_HEIGHT: usize = 32;
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+// The VIP's Hi-res CHIP-8 shows two 64x32 pages stacked on top of each other
+pub const TWO_PAGE_HEIGHT: usize = 64;
+pub const ETI_660_HEIGHT: usize = 48;
+// XO-CHIP draws to up to two bitplanes, giving four colors
+pub const PLANES: usize = 2;
+
+// The screen the CPU draws to, one byte per pixel holding a bit for each plane. Drawing,
+// clearing and scrolling only affect the currently selected planes, and every row that changes
+// is marked dirty until the frontend calls `clear_dirty`.
+#[derive(Debug, Clone)]
+pub struct Framebuffer {
+    pixels: Vec<u8>,
+    width: usize,
+    height: usize,
+    plane_count: usize,
+    selected_planes: u8,
+    dirty_rows: Vec<bool>,
+    wrap_sprites: bool,
+}
+
+impl Framebuffer {
+    pub fn new() -> Self {
+        Self::with_size(LORES_WIDTH, LORES_HEIGHT, PLANES)
+    }
+
+    // `plane_count` is at most 8
+    pub fn with_size(width: usize, height: usize, plane_count: usize) -> Self {
+        Self {
+            pixels: vec![0; width * height],
+            width,
+            height,
+            plane_count: plane_count.min(8),
+            selected_planes: 0b01,
+            dirty_rows: vec![true; height],
+            wrap_sprites: false,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn plane_count(&self) -> usize {
+        self.plane_count
+    }
+
+    // Clears every plane
+    pub fn set_resolution(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.pixels = vec![0; width * height];
+        self.dirty_rows = vec![true; height];
+    }
+
+    // XO-CHIP wraps sprites around the screen edges instead of clipping them
+    pub fn set_sprite_wrapping(&mut self, wrap: bool) {
+        self.wrap_sprites = wrap;
+    }
+
+    // `mask` has bit 0 set for the first plane, bit 1 for the second and so on
+    pub fn select_planes(&mut self, mask: u8) {
+        self.selected_planes = mask & self.all_planes();
+    }
+
+    fn all_planes(&self) -> u8 {
+        ((1u16 << self.plane_count) - 1) as u8
+    }
+
+    fn selected(&self) -> impl Iterator<Item = usize> {
+        let mask = self.selected_planes;
+        (0..self.plane_count).filter(move |plane| mask & (1 << plane) != 0)
+    }
+
+    pub fn selected_plane_count(&self) -> usize {
+        self.selected().count()
+    }
+
+    pub fn clear(&mut self) {
+        let mask = self.selected_planes;
+        for pixel in &mut self.pixels {
+            *pixel &= !mask;
+        }
+        self.dirty_rows.fill(true);
+    }
+
+    // True if the pixel is lit in any plane
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.pixel_planes(x, y) != 0
+    }
+
+    // Returns the pixel's value in each plane as a bitmask, suitable for a four color palette.
+    // Pixels outside the screen are off.
+    pub fn pixel_planes(&self, x: usize, y: usize) -> u8 {
+        if x < self.width && y < self.height { self.pixels[y * self.width + x] } else { 0 }
+    }
+
+    // Every pixel's planes, row by row
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    // Sets the pixel's value in every plane at once
+    pub fn set_pixel_planes(&mut self, x: usize, y: usize, planes: u8) {
+        let planes = planes & self.all_planes();
+        let pixel = &mut self.pixels[y * self.width + x];
+        if *pixel != planes {
+            *pixel = planes;
+            self.dirty_rows[y] = true;
+        }
+    }
+
+    fn plane_pixel(&self, plane: usize, x: usize, y: usize) -> bool {
+        (self.pixels[y * self.width + x] >> plane) & 1 == 1
+    }
+
+    fn set_plane_pixel(&mut self, plane: usize, x: usize, y: usize, on: bool) {
+        let planes = self.pixels[y * self.width + x];
+        let planes = if on { planes | 1 << plane } else { planes & !(1 << plane) };
+        self.set_pixel_planes(x, y, planes);
+    }
+
+    // Sets a pixel in the first plane, for machines that produce whole frames themselves
+    pub fn set_pixel(&mut self, x: usize, y: usize, on: bool) {
+        self.set_plane_pixel(0, x, y, on);
+    }
+
+    // Rows changed since the last `clear_dirty`
+    pub fn dirty_rows(&self) -> impl Iterator<Item = usize> + '_ {
+        self.dirty_rows.iter().enumerate().filter(|(_, dirty)| **dirty).map(|(row, _)| row)
+    }
+
+    // The smallest range of rows covering every dirty row, if any
+    pub fn dirty_row_range(&self) -> Option<Range<usize>> {
+        let first = self.dirty_rows().next()?;
+        let last = self.dirty_rows().last()?;
+        Some(first..last + 1)
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty_rows.contains(&true)
+    }
+
+    pub fn clear_dirty(&mut self) {
+        self.dirty_rows.fill(false);
+    }
+
+    // Converts the screen to RGBA with a color for each combination of planes
+    pub fn to_rgba(&self, palette: &[[u8; 4]]) -> Vec<u8> {
+        self.to_rgba_with(|_, _, planes| palette[planes as usize % palette.len()])
+    }
+
+    // Converts the screen to RGBA, `color` is given each pixel's position and planes
+    pub fn to_rgba_with(&self, mut color: impl FnMut(usize, usize, u8) -> [u8; 4]) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(self.pixels.len() * 4);
+        for (offset, planes) in self.pixels.iter().enumerate() {
+            rgba.extend_from_slice(&color(offset % self.width, offset / self.width, *planes));
+        }
+        rgba
+    }
+
+    // XORs a sprite onto the selected planes, `bytes_per_row` is 1 for regular sprites and 2 for
+    // the 16 pixel wide SCHIP sprites. When two planes are selected the sprite data for the
+    // second plane follows the data for the first. The sprite origin always wraps around the
+    // screen, the rest of the sprite is clipped unless wrapping is enabled. Returns true if any
+    // pixel was turned off.
+    pub fn draw_sprite(&mut self, x: usize, y: usize, sprite: &[u8], bytes_per_row: usize) -> bool {
+        let x = x % self.width;
+        let y = y % self.height;
+        let plane_len = sprite.len() / self.selected_plane_count().max(1);
+        let mut collision = false;
+        let planes: Vec<usize> = self.selected().collect();
+        for (plane, plane_sprite) in planes.into_iter().zip(sprite.chunks(plane_len.max(1))) {
+            for (row, row_bytes) in plane_sprite.chunks(bytes_per_row).enumerate() {
+                let py = y + row;
+                if py >= self.height && !self.wrap_sprites {
+                    break;
+                }
+                for (column, byte) in row_bytes.iter().enumerate() {
+                    for bit in 0..8 {
+                        let px = x + column * 8 + bit;
+                        if px >= self.width && !self.wrap_sprites {
+                            break;
+                        }
+                        if (byte >> (7 - bit)) & 1 == 1 {
+                            let (px, py) = (px % self.width, py % self.height);
+                            let was_on = self.plane_pixel(plane, px, py);
+                            collision |= was_on;
+                            self.set_plane_pixel(plane, px, py, !was_on);
+                        }
+                    }
+                }
+            }
+        }
+        collision
+    }
+
+    pub fn scroll_down(&mut self, rows: usize) {
+        for plane in self.selected().collect::<Vec<_>>() {
+            for y in (0..self.height).rev() {
+                for x in 0..self.width {
+                    let on = y >= rows && self.plane_pixel(plane, x, y - rows);
+                    self.set_plane_pixel(plane, x, y, on);
+                }
+            }
+        }
+    }
+
+    pub fn scroll_up(&mut self, rows: usize) {
+        for plane in self.selected().collect::<Vec<_>>() {
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    let on = y + rows < self.height && self.plane_pixel(plane, x, y + rows);
+                    self.set_plane_pixel(plane, x, y, on);
+                }
+            }
+        }
+    }
+
+    pub fn scroll_right(&mut self, columns: usize) {
+        for plane in self.selected().collect::<Vec<_>>() {
+            for y in 0..self.height {
+                for x in (0..self.width).rev() {
+                    let on = x >= columns && self.plane_pixel(plane, x - columns, y);
+                    self.set_plane_pixel(plane, x, y, on);
+                }
+            }
+        }
+    }
+
+    pub fn scroll_left(&mut self, columns: usize) {
+        for plane in self.selected().collect::<Vec<_>>() {
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    let on = x + columns < self.width && self.plane_pixel(plane, x + columns, y);
+                    self.set_plane_pixel(plane, x, y, on);
+                }
+            }
+        }
+    }
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_draw_sprite_collision() {
+        let mut framebuffer = Framebuffer::new();
+        assert!(!framebuffer.draw_sprite(0, 0, &[0xC0], 1));
+        assert!(framebuffer.pixel(0, 0) && framebuffer.pixel(1, 0));
+        assert!(framebuffer.draw_sprite(1, 0, &[0x80], 1));
+        assert!(!framebuffer.pixel(1, 0));
+    }
+
+    #[test]
+    fn test_draw_sprite_wraps_origin_and_clips() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.draw_sprite(LORES_WIDTH + 62, 0, &[0xFF, 0xFF], 1);
+        assert!(framebuffer.pixel(62, 0) && framebuffer.pixel(63, 1));
+        assert!(!framebuffer.pixel(0, 0) && !framebuffer.pixel(0, 1));
+    }
+
+    #[test]
+    fn test_draw_sprite_wrapping() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.set_sprite_wrapping(true);
+        framebuffer.draw_sprite(62, 31, &[0xFF, 0xFF], 1);
+        assert!(framebuffer.pixel(62, 31) && framebuffer.pixel(0, 31) && framebuffer.pixel(5, 0));
+    }
+
+    #[test]
+    fn test_draw_wide_sprite() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.set_resolution(HIRES_WIDTH, HIRES_HEIGHT);
+        framebuffer.draw_sprite(100, 63, &[0x80, 0x01, 0xFF, 0xFF], 2);
+        assert!(framebuffer.pixel(100, 63) && framebuffer.pixel(115, 63));
+        assert!(!framebuffer.pixel(101, 63));
+    }
+
+    #[test]
+    fn test_draw_sprite_planes() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.select_planes(0b11);
+        framebuffer.draw_sprite(0, 0, &[0xC0, 0x80], 1);
+        assert_eq!(framebuffer.pixel_planes(0, 0), 0b11);
+        assert_eq!(framebuffer.pixel_planes(1, 0), 0b01);
+
+        framebuffer.select_planes(0b10);
+        framebuffer.clear();
+        assert_eq!(framebuffer.pixel_planes(0, 0), 0b01);
+        framebuffer.select_planes(0b00);
+        assert!(!framebuffer.draw_sprite(0, 0, &[0xFF], 1));
+    }
+
+    #[test]
+    fn test_scroll() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.draw_sprite(4, 0, &[0x80], 1);
+        framebuffer.scroll_down(2);
+        assert!(framebuffer.pixel(4, 2) && !framebuffer.pixel(4, 0));
+        framebuffer.scroll_right(4);
+        assert!(framebuffer.pixel(8, 2) && !framebuffer.pixel(4, 2));
+        framebuffer.scroll_left(4);
+        framebuffer.scroll_left(4);
+        assert!(framebuffer.pixel(0, 2) && !framebuffer.pixel(8, 2));
+        framebuffer.scroll_up(2);
+        assert!(framebuffer.pixel(0, 0) && !framebuffer.pixel(0, 2));
+    }
+
+    #[test]
+    fn test_dirty_rows() {
+        let mut framebuffer = Framebuffer::new();
+        assert_eq!(framebuffer.dirty_row_range(), Some(0..LORES_HEIGHT));
+        framebuffer.clear_dirty();
+        assert!(!framebuffer.is_dirty());
+        framebuffer.draw_sprite(0, 3, &[0x80, 0x00, 0x80], 1);
+        assert_eq!(framebuffer.dirty_rows().collect::<Vec<_>>(), vec![3, 5]);
+        assert_eq!(framebuffer.dirty_row_range(), Some(3..6));
+    }
+
+    #[test]
+    fn test_to_rgba() {
+        let mut framebuffer = Framebuffer::with_size(2, 1, 2);
+        framebuffer.set_pixel_planes(1, 0, 0b11);
+        let palette = [[0, 0, 0, 0xFF], [1, 1, 1, 0xFF], [2, 2, 2, 0xFF], [3, 3, 3, 0xFF]];
+        assert_eq!(framebuffer.to_rgba(&palette), vec![0, 0, 0, 0xFF, 3, 3, 3, 0xFF]);
+        assert_eq!(framebuffer.plane_count(), 2);
+    }
+}
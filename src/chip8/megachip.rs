@@ -0,0 +1,265 @@
+pub const MEGACHIP_WIDTH: usize = 256;
+pub const MEGACHIP_HEIGHT: usize = 192;
+
+const PALETTE_SIZE: usize = 256;
+// Palette index 0 is never drawn
+const TRANSPARENT: u8 = 0;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BlendMode {
+    Normal,
+    Opacity25,
+    Opacity50,
+    Opacity75,
+    Add,
+    Multiply,
+}
+
+impl BlendMode {
+    // 080N, unknown modes fall back to normal
+    pub fn from_nibble(mode: u8) -> Self {
+        match mode {
+            1 => BlendMode::Opacity25,
+            2 => BlendMode::Opacity50,
+            3 => BlendMode::Opacity75,
+            4 => BlendMode::Add,
+            5 => BlendMode::Multiply,
+            _ => BlendMode::Normal,
+        }
+    }
+
+    fn blend(self, dst: [u8; 4], src: [u8; 4]) -> [u8; 4] {
+        let mix = |opacity: u16| {
+            let mut out = [0xFF; 4];
+            for channel in 0..3 {
+                out[channel] = ((src[channel] as u16 * opacity + dst[channel] as u16 * (4 - opacity)) / 4) as u8;
+            }
+            out
+        };
+        match self {
+            BlendMode::Normal => src,
+            BlendMode::Opacity25 => mix(1),
+            BlendMode::Opacity50 => mix(2),
+            BlendMode::Opacity75 => mix(3),
+            BlendMode::Add => {
+                let mut out = [0xFF; 4];
+                for channel in 0..3 {
+                    out[channel] = dst[channel].saturating_add(src[channel]);
+                }
+                out
+            },
+            BlendMode::Multiply => {
+                let mut out = [0xFF; 4];
+                for channel in 0..3 {
+                    out[channel] = ((dst[channel] as u16 * src[channel] as u16) / 255) as u8;
+                }
+                out
+            },
+        }
+    }
+}
+
+// A sample started by 060N. The header is a 16 bit sample rate and a 24 bit length followed by
+// a reserved byte, then unsigned 8 bit samples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DigitizedSound {
+    pub sample_rate: u16,
+    pub samples: Vec<u8>,
+    pub looping: bool,
+}
+
+impl DigitizedSound {
+    pub const HEADER_BYTES: usize = 6;
+
+    pub fn length(header: &[u8]) -> usize {
+        match *header {
+            [_, _, a, b, c, ..] => u32::from_be_bytes([0, a, b, c]) as usize,
+            _ => 0,
+        }
+    }
+
+    pub fn new(data: &[u8], looping: bool) -> Self {
+        let sample_rate = match *data {
+            [hi, lo, ..] => u16::from_be_bytes([hi, lo]),
+            _ => 0,
+        };
+        Self {
+            sample_rate,
+            samples: data.get(Self::HEADER_BYTES..).unwrap_or(&[]).to_vec(),
+            looping,
+        }
+    }
+}
+
+// The 256x192 indexed color screen of MegaChip mode. Sprites are drawn to a back buffer which
+// 00E0 presents and clears.
+#[derive(Debug, Clone)]
+pub struct MegaDisplay {
+    indices: Vec<u8>,
+    back: Vec<[u8; 4]>,
+    front: Vec<[u8; 4]>,
+    palette: [[u8; 4]; PALETTE_SIZE],
+    pub sprite_width: usize,
+    pub sprite_height: usize,
+    pub blend_mode: BlendMode,
+    pub collision_color: u8,
+    pub alpha: u8,
+}
+
+impl MegaDisplay {
+    pub fn new() -> Self {
+        Self {
+            indices: vec![TRANSPARENT; MEGACHIP_WIDTH * MEGACHIP_HEIGHT],
+            back: vec![[0, 0, 0, 0xFF]; MEGACHIP_WIDTH * MEGACHIP_HEIGHT],
+            front: vec![[0, 0, 0, 0xFF]; MEGACHIP_WIDTH * MEGACHIP_HEIGHT],
+            palette: [[0, 0, 0, 0xFF]; PALETTE_SIZE],
+            sprite_width: 0,
+            sprite_height: 0,
+            blend_mode: BlendMode::Normal,
+            collision_color: 0,
+            alpha: 0xFF,
+        }
+    }
+
+    // 02NN loads ARGB colors into palette entries 1 through NN
+    pub fn load_palette(&mut self, colors: &[u8]) {
+        for (index, argb) in colors.chunks_exact(4).enumerate().take(PALETTE_SIZE - 1) {
+            self.palette[index + 1] = [argb[1], argb[2], argb[3], argb[0]];
+        }
+    }
+
+    // 03NN and 04NN, a size of 0 means 256
+    pub fn set_sprite_width(&mut self, width: u8) {
+        self.sprite_width = if width == 0 { 256 } else { width as usize };
+    }
+
+    pub fn set_sprite_height(&mut self, height: u8) {
+        self.sprite_height = if height == 0 { 256 } else { height as usize };
+    }
+
+    pub fn sprite_len(&self) -> usize {
+        self.sprite_width * self.sprite_height
+    }
+
+    // Each sprite byte is a palette index. Returns true if a drawn pixel landed on a pixel of
+    // the collision color.
+    pub fn draw_sprite(&mut self, x: usize, y: usize, sprite: &[u8]) -> bool {
+        let mut collision = false;
+        for (offset, &index) in sprite.iter().enumerate() {
+            let px = x + offset % self.sprite_width.max(1);
+            let py = y + offset / self.sprite_width.max(1);
+            if index == TRANSPARENT || px >= MEGACHIP_WIDTH || py >= MEGACHIP_HEIGHT {
+                continue;
+            }
+            let pixel = py * MEGACHIP_WIDTH + px;
+            collision |= self.indices[pixel] != TRANSPARENT && self.indices[pixel] == self.collision_color;
+            self.indices[pixel] = index;
+            self.back[pixel] = self.blend_mode.blend(self.back[pixel], self.palette[index as usize]);
+        }
+        collision
+    }
+
+    // 00E0 in MegaChip mode shows what has been drawn since the last 00E0
+    pub fn present(&mut self) {
+        self.front.copy_from_slice(&self.back);
+        self.indices.fill(TRANSPARENT);
+        self.back.fill([0, 0, 0, 0xFF]);
+    }
+
+    // The presented frame as RGBA, faded by the 05NN screen alpha
+    pub fn to_rgba(&self) -> Vec<u8> {
+        let alpha = self.alpha as u16;
+        self.front.iter()
+            .flat_map(|&[r, g, b, _]| [r, g, b].map(|c| (c as u16 * alpha / 255) as u8).into_iter().chain([0xFF]))
+            .collect()
+    }
+
+    pub fn scroll_up(&mut self, rows: usize) {
+        self.scroll(0, -(rows as isize));
+    }
+
+    pub fn scroll_down(&mut self, rows: usize) {
+        self.scroll(0, rows as isize);
+    }
+
+    pub fn scroll_left(&mut self, columns: usize) {
+        self.scroll(-(columns as isize), 0);
+    }
+
+    pub fn scroll_right(&mut self, columns: usize) {
+        self.scroll(columns as isize, 0);
+    }
+
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let mut indices = vec![TRANSPARENT; self.indices.len()];
+        let mut back = vec![[0, 0, 0, 0xFF]; self.back.len()];
+        for y in 0..MEGACHIP_HEIGHT as isize {
+            for x in 0..MEGACHIP_WIDTH as isize {
+                let (sx, sy) = (x - dx, y - dy);
+                if sx >= 0 && sy >= 0 && (sx as usize) < MEGACHIP_WIDTH && (sy as usize) < MEGACHIP_HEIGHT {
+                    let from = sy as usize * MEGACHIP_WIDTH + sx as usize;
+                    let to = y as usize * MEGACHIP_WIDTH + x as usize;
+                    indices[to] = self.indices[from];
+                    back[to] = self.back[from];
+                }
+            }
+        }
+        self.indices = indices;
+        self.back = back;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_draw_and_present() {
+        let mut display = MegaDisplay::new();
+        display.load_palette(&[0xFF, 0x11, 0x22, 0x33]);
+        display.set_sprite_width(2);
+        display.set_sprite_height(1);
+        display.collision_color = 1;
+        assert!(!display.draw_sprite(10, 0, &[1, 0]));
+        assert!(display.draw_sprite(10, 0, &[1, 1]));
+        assert_eq!(display.to_rgba()[40..44], [0, 0, 0, 0xFF]);
+
+        display.present();
+        assert_eq!(display.to_rgba()[40..44], [0x11, 0x22, 0x33, 0xFF]);
+        assert_eq!(display.to_rgba()[44..48], [0x11, 0x22, 0x33, 0xFF]);
+        assert_eq!(display.to_rgba()[48..52], [0, 0, 0, 0xFF]);
+    }
+
+    #[test]
+    fn test_blend_modes() {
+        let dst = [100, 100, 100, 0xFF];
+        let src = [200, 200, 200, 0xFF];
+        assert_eq!(BlendMode::Opacity50.blend(dst, src), [150, 150, 150, 0xFF]);
+        assert_eq!(BlendMode::Add.blend(dst, src), [255, 255, 255, 0xFF]);
+        assert_eq!(BlendMode::Multiply.blend([255, 0, 255, 0xFF], src), [200, 0, 200, 0xFF]);
+    }
+
+    #[test]
+    fn test_scroll() {
+        let mut display = MegaDisplay::new();
+        display.load_palette(&[0xFF, 0xFF, 0xFF, 0xFF]);
+        display.set_sprite_width(1);
+        display.set_sprite_height(1);
+        display.draw_sprite(0, 0, &[1]);
+        display.scroll_down(2);
+        display.scroll_right(4);
+        display.present();
+        let pixel = (2 * MEGACHIP_WIDTH + 4) * 4;
+        assert_eq!(display.to_rgba()[pixel..pixel + 4], [0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(display.to_rgba()[0..4], [0, 0, 0, 0xFF]);
+    }
+
+    #[test]
+    fn test_digitized_sound() {
+        let data = [0x1F, 0x40, 0x00, 0x00, 0x02, 0x00, 0x80, 0x7F];
+        assert_eq!(DigitizedSound::length(&data), 2);
+        let sound = DigitizedSound::new(&data, true);
+        assert_eq!(sound.sample_rate, 8000);
+        assert_eq!(sound.samples, vec![0x80, 0x7F]);
+    }
+}
@@ -1,31 +1,57 @@
 use std::ops::{Add, AddAssign};
 
 #[derive(Debug)]
-pub struct Memory([u8; 4096]);
+pub struct Memory(Vec<u8>);
 impl Memory {
+    pub const DEFAULT_SIZE: usize = 4096;
+    pub const XO_CHIP_SIZE: usize = 65536;
+    // Large enough for the 24 bit addresses of MegaChip's 01NN NNNN
+    pub const MEGACHIP_SIZE: usize = 0x100_0000;
+
     pub fn new() -> Self {
-        Self([0; 4096])
+        Self::with_size(Self::DEFAULT_SIZE)
+    }
+
+    // Addresses wrap around at `size`, which must be a power of two
+    pub fn with_size(size: usize) -> Self {
+        Self(vec![0; size])
+    }
+
+    pub fn size(&self) -> usize {
+        self.0.len()
+    }
+
+    fn offset(&self, addr: MemoryAddress) -> usize {
+        addr.0 as usize & (self.0.len() - 1)
     }
 
     pub fn read_bytes(&self, addr: MemoryAddress, num_bytes: usize) -> &[u8] {
-        let start = (addr.0 & 0x0FFF) as usize;
-        let end = start + num_bytes;
-        let end = if end > 4096 { 4096 } else { end };
+        let start = self.offset(addr);
+        let end = (start + num_bytes).min(self.0.len());
         &self.0[start..end]
     }
 
     pub fn write_bytes(&mut self, addr: MemoryAddress, data: &[u8]) {
-        let start = (addr.0 & 0x0FFF) as usize;
-        let end = start + data.len();
-        self.0[start..end].copy_from_slice(data);
+        let start = self.offset(addr);
+        let end = (start + data.len()).min(self.0.len());
+        self.0[start..end].copy_from_slice(&data[..end - start]);
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
-pub struct MemoryAddress(pub u16);
+pub struct MemoryAddress(pub u32);
 impl MemoryAddress {
     pub const ZERO: Self = Self(0);
+    pub const BIG_DIGITS_START: Self = Self(0x50);
     pub const PROGRAM_START: Self = Self(0x200);
+    pub const TWO_PAGE_PROGRAM_START: Self = Self(0x2C0);
+    pub const CHIP8X_PROGRAM_START: Self = Self(0x300);
 
     pub fn next_instruction(&mut self) {
         *self += 2;
@@ -36,13 +62,13 @@ impl Add<u8> for MemoryAddress {
     type Output = Self;
 
     fn add(self, other: u8) -> Self {
-        Self(self.0.wrapping_add(other as u16))
+        Self(self.0.wrapping_add(other as u32))
     }
 }
 
 impl AddAssign<u8> for MemoryAddress {
     fn add_assign(&mut self, other: u8) {
-        self.0 = self.0.wrapping_add(other as u16);
+        self.0 = self.0.wrapping_add(other as u32);
     }
 }
 
@@ -66,4 +92,16 @@ mod tests {
         let result = memory.read_bytes(MemoryAddress::ZERO, 4);
         assert_eq!(result, data);
     }
+
+    #[test]
+    fn test_memory_wraps_at_size() {
+        let mut memory = Memory::new();
+        memory.write_bytes(MemoryAddress(0x1FFF), &[0xAB, 0xCD]);
+        assert_eq!(memory.read_bytes(MemoryAddress(0x0FFF), 2), &[0xAB]);
+
+        let mut memory = Memory::with_size(Memory::XO_CHIP_SIZE);
+        memory.write_bytes(MemoryAddress(0x1FFF), &[0xAB]);
+        assert_eq!(memory.read_bytes(MemoryAddress(0x0FFF), 1), &[0x00]);
+        assert_eq!(memory.read_bytes(MemoryAddress(0x1FFF), 1), &[0xAB]);
+    }
 }
@@ -0,0 +1,174 @@
+use super::framebuffer::{ETI_660_HEIGHT, LORES_HEIGHT, LORES_WIDTH};
+use super::{Font, Memory, MemoryAddress, Variant};
+use std::str::FromStr;
+
+// Where a historical machine keeps things and how big it is. Any of the fields can be
+// customized, they are all public.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Platform {
+    pub variant: Variant,
+    // Must be a power of two
+    pub memory_size: usize,
+    pub program_start: MemoryAddress,
+    pub font_start: MemoryAddress,
+    // The SCHIP 8x10 font
+    pub big_font_start: MemoryAddress,
+    pub font: Font,
+    // The resolution at startup
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Platform {
+    // The original COSMAC VIP interpreter. Its font is really in the monitor ROM, here it's at 000.
+    pub fn vip() -> Self {
+        Self {
+            variant: Variant::Chip8,
+            memory_size: Memory::DEFAULT_SIZE,
+            program_start: MemoryAddress::PROGRAM_START,
+            font_start: MemoryAddress::ZERO,
+            big_font_start: MemoryAddress::BIG_DIGITS_START,
+            font: Font::default(),
+            width: LORES_WIDTH,
+            height: LORES_HEIGHT,
+        }
+    }
+
+    // The ETI-660 keeps its interpreter below 600 and has a 64x48 display
+    pub fn eti_660() -> Self {
+        Self {
+            program_start: MemoryAddress(0x600),
+            font: Font::eti_660(),
+            height: ETI_660_HEIGHT,
+            ..Self::vip()
+        }
+    }
+
+    pub fn dream_6800() -> Self {
+        Self {
+            font: Font::dream_6800(),
+            ..Self::vip()
+        }
+    }
+
+    // SCHIP and XO-CHIP interpreters usually keep the font at 050 with the big font after it
+    pub fn schip() -> Self {
+        Self {
+            variant: Variant::SuperChip,
+            font_start: MemoryAddress(0x50),
+            big_font_start: MemoryAddress(0xA0),
+            ..Self::vip()
+        }
+    }
+
+    pub fn xo_chip() -> Self {
+        Self {
+            variant: Variant::XoChip,
+            memory_size: Memory::XO_CHIP_SIZE,
+            ..Self::schip()
+        }
+    }
+
+    // The platform each variant normally runs on
+    pub fn for_variant(variant: Variant) -> Self {
+        match variant {
+            Variant::Chip8 => Self::vip(),
+            Variant::SuperChip => Self::schip(),
+            Variant::XoChip => Self::xo_chip(),
+            Variant::HiresChip8 => Self { variant, ..Self::vip() },
+            Variant::Chip8X => Self {
+                variant,
+                program_start: MemoryAddress::CHIP8X_PROGRAM_START,
+                ..Self::vip()
+            },
+            Variant::MegaChip => Self {
+                variant,
+                memory_size: Memory::MEGACHIP_SIZE,
+                ..Self::schip()
+            },
+        }
+    }
+
+    // Fails if the fonts overlap each other or the program start, or don't fit in memory
+    pub fn check(&self) -> Result<(), String> {
+        let small = self.font_start.0 as usize..self.font_start.0 as usize + self.font.small.len();
+        let big = self.big_font_start.0 as usize..self.big_font_start.0 as usize + self.font.big.len();
+        let program_start = self.program_start.0 as usize;
+        if small.start < big.end && big.start < small.end {
+            return Err(format!("the font at {:#X} overlaps the big font at {:#X}", small.start, big.start));
+        }
+        for (name, font) in [("font", &small), ("big font", &big)] {
+            if font.contains(&program_start) {
+                return Err(format!(
+                    "the {} at {:#X} overlaps the program start {:#X}",
+                    name, font.start, program_start
+                ));
+            }
+            if font.end > self.memory_size {
+                return Err(format!(
+                    "the {} at {:#X} doesn't fit in {} bytes of memory",
+                    name, font.start, self.memory_size
+                ));
+            }
+        }
+        if program_start >= self.memory_size {
+            return Err(format!(
+                "the program start {:#X} is outside {} bytes of memory",
+                program_start, self.memory_size
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for Platform {
+    fn default() -> Self {
+        Self::vip()
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "vip" => Ok(Self::vip()),
+            "eti660" | "eti-660" => Ok(Self::eti_660()),
+            "dream6800" | "dream-6800" => Ok(Self::dream_6800()),
+            "schip" => Ok(Self::schip()),
+            "xochip" | "xo-chip" => Ok(Self::xo_chip()),
+            _ => Err(format!("unknown platform: {}", name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_named_platforms() {
+        assert_eq!("eti660".parse::<Platform>().unwrap().program_start, MemoryAddress(0x600));
+        assert_eq!("xochip".parse::<Platform>().unwrap().memory_size, Memory::XO_CHIP_SIZE);
+        assert_eq!("schip".parse::<Platform>().unwrap().font_start, MemoryAddress(0x50));
+        assert!("c64".parse::<Platform>().is_err());
+    }
+
+    #[test]
+    fn test_for_variant() {
+        assert_eq!(Platform::for_variant(Variant::Chip8), Platform::vip());
+        assert_eq!(Platform::for_variant(Variant::Chip8X).program_start, MemoryAddress::CHIP8X_PROGRAM_START);
+        assert_eq!(Platform::for_variant(Variant::MegaChip).variant, Variant::MegaChip);
+    }
+
+    #[test]
+    fn test_check() {
+        for platform in ["vip", "eti660", "dream6800", "schip", "xochip"] {
+            assert_eq!(platform.parse::<Platform>().unwrap().check(), Ok(()));
+        }
+        assert!(Platform { font_start: MemoryAddress(0x60), ..Platform::vip() }.check().is_err());
+        assert!(Platform { font_start: MemoryAddress(0x1D0), ..Platform::vip() }.check().is_err());
+        assert!(Platform { memory_size: 0x200, ..Platform::vip() }.check().is_err());
+        assert!(Platform { big_font_start: MemoryAddress(0xFF0), ..Platform::vip() }.check().is_err());
+    }
+}
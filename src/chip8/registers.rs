@@ -93,6 +93,17 @@ impl Registers {
     pub fn xor_register(&mut self, vx: u8, vy: u8) {
         self[vx] ^= self[vy];
     }
+
+    pub fn tick_timers(&mut self) {
+        self[Self::DT] = self[Self::DT].saturating_sub(1);
+        self[Self::ST] = self[Self::ST].saturating_sub(1);
+    }
+}
+
+impl Default for Registers {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Index<u8> for Registers {
@@ -149,4 +160,13 @@ mod tests {
         assert_eq!(registers[Registers::V0], 0xFF);
         assert_eq!(registers[Registers::V1], 0x0F);
     }
+
+    #[test]
+    fn test_tick_timers() {
+        let mut registers = Registers::new();
+        registers.load_scalar(Registers::DT, 2);
+        registers.tick_timers();
+        assert_eq!(registers[Registers::DT], 1);
+        assert_eq!(registers[Registers::ST], 0);
+    }
 }
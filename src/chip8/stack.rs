@@ -23,6 +23,10 @@ impl Stack {
         self.pointer = (self.pointer - 1) % self.data.len();
         self.data[self.pointer]
     }
+
+    pub fn as_slice(&self) -> &[MemoryAddress] {
+        &self.data[..self.pointer]
+    }
 }
 
 
@@ -0,0 +1,32 @@
+use super::{Framebuffer, Memory, MemoryAddress, Registers};
+use std::fmt;
+
+// The machine state a 0NNN call can see and change
+pub struct SysContext<'a> {
+    pub registers: &'a mut Registers,
+    pub i: &'a mut MemoryAddress,
+    pub memory: &'a mut Memory,
+    pub framebuffer: &'a mut Framebuffer,
+}
+
+// Stands in for the machine code routine at `addr` that a 0NNN instruction calls. Returns false
+// if it doesn't know the routine so the next handler gets a chance.
+pub trait SysHandler {
+    fn call(&mut self, addr: MemoryAddress, context: &mut SysContext) -> bool;
+}
+
+impl<F: FnMut(MemoryAddress, &mut SysContext) -> bool> SysHandler for F {
+    fn call(&mut self, addr: MemoryAddress, context: &mut SysContext) -> bool {
+        self(addr, context)
+    }
+}
+
+// Handlers in the order they were added
+#[derive(Default)]
+pub struct SysHandlers(pub Vec<Box<dyn SysHandler>>);
+
+impl fmt::Debug for SysHandlers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SysHandlers({})", self.0.len())
+    }
+}
@@ -0,0 +1,677 @@
+use crate::chip8::{Chip8, Font, MemoryAddress, Platform, Variant};
+use crate::palette::{parse_color, Palette, Theme};
+use crate::vip::Vip;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+pub const USAGE: &str = "\
+Usage: wgpuchip8 [OPTIONS] [ROM]
+
+Runs ROM (defaults to the built in IBM logo) in a window.
+
+Options:
+  --variant <NAME>   Instruction set to run: chip8 (default), schip, xochip, hires, chip8x
+                     or megachip
+  --platform <NAME>  Machine to lay out memory like: vip (default), eti660, dream6800, schip or xochip
+  --program-start <ADDR>, --font-start <ADDR>, --memory-size <BYTES>
+                     Customize the platform, numbers starting with 0x are hexadecimal
+  --font <NAME>      Digit glyphs: default, vip, dream6800, eti660 or fishnchips
+  --font-file <FILE> Load the glyphs from 80 bytes of 4x5 digits, optionally followed by 160 bytes
+                     of 8x10 digits
+  --vip-timing       Run as many instructions a frame as the original VIP interpreter would
+  --display-wait     Wait for the next frame after drawing a sprite, like the VIP
+  --rpl <FILE>       Where SCHIP persists its RPL user flags (defaults to ROM with an .rpl extension)
+  --vip-monitor <FILE>
+                     Emulate a COSMAC VIP at the 1802 level with this 512 byte monitor ROM
+  --vip-interpreter <FILE>
+                     The CHIP-8 interpreter image the VIP runs, required with --vip-monitor
+  --renderer <NAME>  Draw the window with wgpu, software or auto (default), which falls back to
+                     software when there's no usable GPU
+  --backend <NAME>   Graphics API for wgpu: vulkan, metal, dx12, dx11, gl or auto (default, honours
+                     WGPU_BACKEND)
+  --no-vsync         Present frames as soon as they're drawn instead of waiting for vertical sync
+  --scale <MODE>     Scale the display to the window: fit (default), integer or stretch
+  --fullscreen       Start fullscreen, F11 toggles it
+  --theme <NAME>     Colors to draw with: classic (default), amber, green, lcd, high-contrast or
+                     color-blind. F2 cycles through them
+  --palette <COLORS> Four RRGGBB colors separated by commas, for the background, plane 1, plane 2
+                     and both planes
+  --foreground <COLOR>, --background <COLOR>, --border <COLOR>
+                     Override a single RRGGBB color, the border fills the window around the display
+  --persistence <MODE>
+                     Hide flicker: blend[:STRENGTH] fades erased pixels out (strength 0 to 0.95,
+                     default 0.5), or[:FRAMES] keeps pixels lit for that many frames (default 2)
+  --upscale <NAME>   Smooth the display's pixels with scale2x (or epx), scale3x, xbr or none (default)
+  --crt              Draw the display like a CRT monitor, F3 toggles it (wgpu only)
+  --crt-scanlines <AMOUNT>, --crt-curvature <AMOUNT>, --crt-bloom <AMOUNT>, --crt-vignette <AMOUNT>
+                     Strength of each CRT effect from 0 (off) to 1
+  --shader-dir <DIR> Run the .wgsl post-processing shaders in DIR in order of their names, reloading
+                     them when they change (wgpu only)
+  --capture-dir <DIR>
+                     Where F9 saves screenshots and F10 saves recordings (defaults to the current
+                     directory)
+  --capture-scale <N>
+                     Pixels per display pixel in screenshots and recordings (default 4)
+  --record-format <NAME>
+                     Save recordings as an animated gif (default) or apng
+  --screenshot <FILE>
+                     Run without a window and save a PNG screenshot after --frames frames
+  --frames <N>       Frames to run before a --screenshot is taken (default 60)
+  --terminal         Render to the terminal instead of opening a window
+  --braille          Use braille characters instead of half blocks in the terminal
+  -h, --help         Print this message";
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Frontend {
+    Window,
+    Terminal,
+    // Runs without any output and saves a screenshot
+    Headless,
+}
+
+// Which renderer draws the window
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RendererKind {
+    // The GPU if there's a usable adapter, otherwise the CPU
+    Auto,
+    Wgpu,
+    Software,
+}
+
+impl FromStr for RendererKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "auto" => Ok(RendererKind::Auto),
+            "wgpu" | "gpu" => Ok(RendererKind::Wgpu),
+            "software" | "cpu" => Ok(RendererKind::Software),
+            _ => Err(format!("unknown renderer: {}", name)),
+        }
+    }
+}
+
+// How the display is scaled to the window, always centered with the border color around it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ScaleMode {
+    // The largest whole multiple of the display's size that fits, so every pixel is the same size
+    Integer,
+    // As large as fits while keeping the aspect ratio
+    Fit,
+    // Fills the window
+    Stretch,
+}
+
+impl FromStr for ScaleMode {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "integer" => Ok(ScaleMode::Integer),
+            "fit" => Ok(ScaleMode::Fit),
+            "stretch" => Ok(ScaleMode::Stretch),
+            _ => Err(format!("unknown scale mode: {}", name)),
+        }
+    }
+}
+
+// How earlier frames are mixed into the presented one to hide flicker
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Persistence {
+    Off,
+    // Each frame moves the presented colors `1 - strength` of the way to the screen's
+    Blend { strength: f32 },
+    // Pixels lit in any of the last `frames` frames are shown lit
+    Or { frames: usize },
+}
+
+impl FromStr for Persistence {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        let (name, amount) = match mode.split_once(':') {
+            Some((name, amount)) => (name, Some(amount)),
+            None => (mode, None),
+        };
+        let invalid = || format!("invalid persistence: {}", mode);
+        match (name, amount) {
+            ("off", None) => Ok(Persistence::Off),
+            ("blend", None) => Ok(Persistence::Blend { strength: 0.5 }),
+            ("blend", Some(strength)) => match strength.parse::<f32>() {
+                Ok(strength) if (0.0..=0.95).contains(&strength) => Ok(Persistence::Blend { strength }),
+                _ => Err(invalid()),
+            },
+            ("or", None) => Ok(Persistence::Or { frames: 2 }),
+            ("or", Some(frames)) => match frames.parse::<usize>() {
+                Ok(frames) if frames > 0 => Ok(Persistence::Or { frames }),
+                _ => Err(invalid()),
+            },
+            _ => Err(invalid()),
+        }
+    }
+}
+
+// Pixel art upscaler the display goes through before it's scaled to the window
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Upscaler {
+    None,
+    // Also known as EPX, doubles the size and rounds off diagonal steps
+    Scale2x,
+    // Triples the size in the same way
+    Scale3x,
+    // Doubles the size and blends along edges it finds in a 5x5 neighbourhood, smoother than
+    // Scale2x on diagonals
+    Xbr,
+}
+
+impl Upscaler {
+    // How many times wider and taller the output is
+    pub fn factor(self) -> usize {
+        match self {
+            Upscaler::None => 1,
+            Upscaler::Scale2x | Upscaler::Xbr => 2,
+            Upscaler::Scale3x => 3,
+        }
+    }
+}
+
+impl FromStr for Upscaler {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "none" => Ok(Upscaler::None),
+            "scale2x" | "epx" => Ok(Upscaler::Scale2x),
+            "scale3x" => Ok(Upscaler::Scale3x),
+            "xbr" => Ok(Upscaler::Xbr),
+            _ => Err(format!("unknown upscaler: {}", name)),
+        }
+    }
+}
+
+// The file format F10 records to
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RecordFormat {
+    Gif,
+    Apng,
+}
+
+impl FromStr for RecordFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "gif" => Ok(RecordFormat::Gif),
+            "apng" => Ok(RecordFormat::Apng),
+            _ => Err(format!("unknown recording format: {}", name)),
+        }
+    }
+}
+
+// Strengths of the CRT effects, from 0 (off) to 1
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CrtSettings {
+    pub scanlines: f32,
+    pub curvature: f32,
+    pub bloom: f32,
+    pub vignette: f32,
+}
+
+impl Default for CrtSettings {
+    fn default() -> Self {
+        Self {
+            scanlines: 0.5,
+            curvature: 0.3,
+            bloom: 0.3,
+            vignette: 0.4,
+        }
+    }
+}
+
+// The graphics API the wgpu renderer uses
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum GpuBackend {
+    // Whatever WGPU_BACKEND says, or the best one available
+    Auto,
+    Vulkan,
+    Metal,
+    Dx12,
+    Dx11,
+    Gl,
+}
+
+impl FromStr for GpuBackend {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "auto" => Ok(GpuBackend::Auto),
+            "vulkan" => Ok(GpuBackend::Vulkan),
+            "metal" => Ok(GpuBackend::Metal),
+            "dx12" => Ok(GpuBackend::Dx12),
+            "dx11" => Ok(GpuBackend::Dx11),
+            "gl" => Ok(GpuBackend::Gl),
+            _ => Err(format!("unknown backend: {}", name)),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TerminalGlyphs {
+    HalfBlocks,
+    Braille,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub rom: Option<PathBuf>,
+    pub platform: Platform,
+    pub font_file: Option<PathBuf>,
+    pub rpl: Option<PathBuf>,
+    pub vip_monitor: Option<PathBuf>,
+    pub vip_interpreter: Option<PathBuf>,
+    pub vip_timing: bool,
+    pub display_wait: bool,
+    pub frontend: Frontend,
+    pub renderer: RendererKind,
+    pub gpu_backend: GpuBackend,
+    pub vsync: bool,
+    pub scale: ScaleMode,
+    pub fullscreen: bool,
+    pub theme: Theme,
+    // The theme's palette with any colors given on the command line
+    pub palette: Palette,
+    pub persistence: Persistence,
+    pub upscaler: Upscaler,
+    pub crt: bool,
+    pub crt_settings: CrtSettings,
+    pub shader_dir: Option<PathBuf>,
+    pub capture_dir: PathBuf,
+    pub capture_scale: usize,
+    pub record_format: RecordFormat,
+    // Where --screenshot saves to, set for the headless frontend
+    pub screenshot: Option<PathBuf>,
+    pub screenshot_frames: usize,
+    pub terminal_glyphs: TerminalGlyphs,
+    pub help: bool,
+}
+
+impl Config {
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut config = Self::default();
+        let (mut variant, mut platform) = (None::<Variant>, None::<Platform>);
+        let (mut program_start, mut font_start, mut memory_size) = (None, None, None);
+        let mut font = None::<Font>;
+        let (mut colors, mut foreground, mut background, mut border) = (None, None, None, None);
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--variant" => variant = Some(value(&arg, args.next())?.parse()?),
+                "--platform" => platform = Some(value(&arg, args.next())?.parse()?),
+                "--program-start" => program_start = Some(number(&arg, args.next())?),
+                "--font-start" => font_start = Some(number(&arg, args.next())?),
+                "--memory-size" => memory_size = Some(number(&arg, args.next())?),
+                "--font" => font = Some(value(&arg, args.next())?.parse()?),
+                "--font-file" => config.font_file = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--rpl" => config.rpl = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--vip-monitor" => config.vip_monitor = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--vip-interpreter" => config.vip_interpreter = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--vip-timing" => config.vip_timing = true,
+                "--display-wait" => config.display_wait = true,
+                "--renderer" => config.renderer = value(&arg, args.next())?.parse()?,
+                "--backend" => config.gpu_backend = value(&arg, args.next())?.parse()?,
+                "--no-vsync" => config.vsync = false,
+                "--scale" => config.scale = value(&arg, args.next())?.parse()?,
+                "--fullscreen" => config.fullscreen = true,
+                "--theme" => config.theme = value(&arg, args.next())?.parse()?,
+                "--palette" => colors = Some(parse_palette(&value(&arg, args.next())?)?),
+                "--foreground" => foreground = Some(parse_color(&value(&arg, args.next())?)?),
+                "--background" => background = Some(parse_color(&value(&arg, args.next())?)?),
+                "--border" => border = Some(parse_color(&value(&arg, args.next())?)?),
+                "--persistence" => config.persistence = value(&arg, args.next())?.parse()?,
+                "--upscale" => config.upscaler = value(&arg, args.next())?.parse()?,
+                "--crt" => config.crt = true,
+                "--crt-scanlines" => config.crt_settings.scanlines = fraction(&arg, args.next())?,
+                "--crt-curvature" => config.crt_settings.curvature = fraction(&arg, args.next())?,
+                "--crt-bloom" => config.crt_settings.bloom = fraction(&arg, args.next())?,
+                "--crt-vignette" => config.crt_settings.vignette = fraction(&arg, args.next())?,
+                "--shader-dir" => config.shader_dir = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--capture-dir" => config.capture_dir = PathBuf::from(value(&arg, args.next())?),
+                "--capture-scale" => config.capture_scale = positive(&arg, args.next())?,
+                "--record-format" => config.record_format = value(&arg, args.next())?.parse()?,
+                "--screenshot" => {
+                    config.frontend = Frontend::Headless;
+                    config.screenshot = Some(PathBuf::from(value(&arg, args.next())?));
+                },
+                "--frames" => config.screenshot_frames = positive(&arg, args.next())?,
+                "--terminal" => config.frontend = Frontend::Terminal,
+                "--braille" => config.terminal_glyphs = TerminalGlyphs::Braille,
+                "-h" | "--help" => config.help = true,
+                flag if flag.starts_with('-') => return Err(format!("unknown option: {}", flag)),
+                _ if config.rom.is_some() => return Err(format!("unexpected argument: {}", arg)),
+                _ => config.rom = Some(PathBuf::from(arg)),
+            }
+        }
+        if config.vip_monitor.is_some() != config.vip_interpreter.is_some() {
+            return Err("--vip-monitor and --vip-interpreter must be given together".to_string());
+        }
+        // The VIP images bring their own interpreter, memory layout and font
+        let platform_flags = variant.is_some() || platform.is_some() || program_start.is_some()
+            || font_start.is_some() || memory_size.is_some() || font.is_some() || config.font_file.is_some();
+        if config.vip_monitor.is_some() && platform_flags {
+            return Err(
+                "--variant, --platform, --program-start, --font-start, --memory-size, --font and --font-file \
+                 can't be used with --vip-monitor and --vip-interpreter".to_string()
+            );
+        }
+        // A variant on its own picks the platform it normally runs on
+        config.platform = match (platform, variant) {
+            (Some(platform), Some(variant)) => Platform { variant, ..platform },
+            (Some(platform), None) => platform,
+            (None, variant) => Platform::for_variant(variant.unwrap_or(Variant::Chip8)),
+        };
+        if let Some(addr) = program_start {
+            config.platform.program_start = MemoryAddress(addr);
+        }
+        if let Some(font) = font {
+            config.platform.font = font;
+        }
+        // The big font moves along, right after the small one
+        if let Some(addr) = font_start {
+            config.platform.font_start = MemoryAddress(addr);
+            config.platform.big_font_start = MemoryAddress(addr + config.platform.font.small.len() as u32);
+        }
+        config.palette = config.theme.palette();
+        if let Some(colors) = colors {
+            config.palette.colors = colors;
+        }
+        if let Some(color) = background {
+            config.palette.colors[0] = color;
+        }
+        if let Some(color) = foreground {
+            config.palette.colors[1] = color;
+        }
+        if let Some(color) = border {
+            config.palette.border = color;
+        }
+        if let Some(size) = memory_size {
+            if !size.is_power_of_two() {
+                return Err(format!("--memory-size must be a power of two: {}", size));
+            }
+            config.platform.memory_size = size as usize;
+        }
+        config.platform.check()?;
+        Ok(config)
+    }
+
+    pub fn load_rom(&self) -> std::io::Result<Vec<u8>> {
+        match &self.rom {
+            Some(path) => std::fs::read(path),
+            None => Ok(include_bytes!("../roms/2-ibm-logo.ch8").to_vec()),
+        }
+    }
+
+    pub fn rpl_path(&self) -> Option<PathBuf> {
+        self.rpl.clone().or_else(|| self.rom.as_ref().map(|rom| rom.with_extension("rpl")))
+    }
+
+    // Creates a Chip8 set up for this configuration with `program` loaded, failing only if the
+    // font file or VIP images can't be read
+    pub fn create_chip8(&self, program: &[u8]) -> std::io::Result<Chip8> {
+        let mut chip8 = match (&self.vip_monitor, &self.vip_interpreter) {
+            (Some(monitor), Some(interpreter)) => {
+                Chip8::with_vip(Vip::new(&std::fs::read(monitor)?, &std::fs::read(interpreter)?))
+            },
+            _ => {
+                let mut platform = self.platform.clone();
+                if let Some(path) = &self.font_file {
+                    platform.font = Font::from_bytes(&std::fs::read(path)?)
+                        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+                }
+                Chip8::with_platform(platform)
+            },
+        };
+        chip8.set_vip_timing(self.vip_timing);
+        chip8.set_display_wait(self.display_wait);
+        if let Some(path) = self.rpl_path() {
+            chip8.set_rpl_path(path);
+        }
+        chip8.load_program(program);
+        Ok(chip8)
+    }
+}
+
+fn value(flag: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("{} requires a value", flag))
+}
+
+fn fraction(flag: &str, value: Option<String>) -> Result<f32, String> {
+    let value = self::value(flag, value)?;
+    match value.parse::<f32>() {
+        Ok(fraction) if (0.0..=1.0).contains(&fraction) => Ok(fraction),
+        _ => Err(format!("{} requires a number from 0 to 1: {}", flag, value)),
+    }
+}
+
+fn positive(flag: &str, value: Option<String>) -> Result<usize, String> {
+    match number(flag, value)? {
+        0 => Err(format!("{} must be at least 1", flag)),
+        number => Ok(number as usize),
+    }
+}
+
+fn parse_palette(value: &str) -> Result<[[u8; 4]; 4], String> {
+    let colors = value.split(',').map(parse_color).collect::<Result<Vec<_>, _>>()?;
+    colors.try_into().map_err(|_| format!("--palette requires four colors: {}", value))
+}
+
+fn number(flag: &str, value: Option<String>) -> Result<u32, String> {
+    let value = self::value(flag, value)?;
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    parsed.map_err(|_| format!("{} requires a number: {}", flag, value))
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            rom: None,
+            platform: Platform::default(),
+            font_file: None,
+            rpl: None,
+            vip_monitor: None,
+            vip_interpreter: None,
+            vip_timing: false,
+            display_wait: false,
+            frontend: Frontend::Window,
+            renderer: RendererKind::Auto,
+            gpu_backend: GpuBackend::Auto,
+            vsync: true,
+            scale: ScaleMode::Fit,
+            fullscreen: false,
+            theme: Theme::Classic,
+            palette: Palette::default(),
+            persistence: Persistence::Off,
+            upscaler: Upscaler::None,
+            crt: false,
+            crt_settings: CrtSettings::default(),
+            shader_dir: None,
+            capture_dir: PathBuf::from("."),
+            capture_scale: 4,
+            record_format: RecordFormat::Gif,
+            screenshot: None,
+            screenshot_frames: 60,
+            terminal_glyphs: TerminalGlyphs::HalfBlocks,
+            help: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Config, String> {
+        Config::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_defaults() {
+        assert_eq!(parse(&[]), Ok(Config::default()));
+    }
+
+    #[test]
+    fn test_vip_timing() {
+        let config = parse(&["--vip-timing", "--display-wait"]).unwrap();
+        assert!(config.vip_timing && config.display_wait);
+    }
+
+    #[test]
+    fn test_renderer() {
+        assert_eq!(parse(&["--renderer", "software"]).unwrap().renderer, RendererKind::Software);
+        assert_eq!(parse(&["--renderer", "wgpu"]).unwrap().renderer, RendererKind::Wgpu);
+        assert!(parse(&["--renderer", "opengl"]).is_err());
+        let config = parse(&["--backend", "gl", "--no-vsync"]).unwrap();
+        assert_eq!(config.gpu_backend, GpuBackend::Gl);
+        assert!(!config.vsync);
+        let config = parse(&["--scale", "integer", "--fullscreen"]).unwrap();
+        assert_eq!(config.scale, ScaleMode::Integer);
+        assert!(config.fullscreen);
+        assert!(parse(&["--scale", "zoom"]).is_err());
+    }
+
+    #[test]
+    fn test_colors() {
+        let config = parse(&["--theme", "amber"]).unwrap();
+        assert_eq!(config.theme, Theme::Amber);
+        assert_eq!(config.palette, Theme::Amber.palette());
+        let config = parse(&["--palette", "000000,FFFFFF,FF0000,00FF00", "--foreground", "#0000FF"]).unwrap();
+        assert_eq!(config.palette.colors[1], [0x00, 0x00, 0xFF, 0xFF]);
+        assert_eq!(config.palette.colors[2], [0xFF, 0x00, 0x00, 0xFF]);
+        let config = parse(&["--theme", "lcd", "--border", "123456", "--background", "FFFFFF"]).unwrap();
+        assert_eq!(config.palette.border, [0x12, 0x34, 0x56, 0xFF]);
+        assert_eq!(config.palette.background(), [0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(config.palette.foreground(), Theme::Lcd.palette().foreground());
+        assert!(parse(&["--palette", "000000,FFFFFF"]).is_err());
+        assert!(parse(&["--theme", "sepia"]).is_err());
+    }
+
+    #[test]
+    fn test_persistence() {
+        let persistence = |mode| parse(&["--persistence", mode]).map(|config| config.persistence);
+        assert_eq!(persistence("blend"), Ok(Persistence::Blend { strength: 0.5 }));
+        assert_eq!(persistence("blend:0.8"), Ok(Persistence::Blend { strength: 0.8 }));
+        assert_eq!(persistence("or:3"), Ok(Persistence::Or { frames: 3 }));
+        assert_eq!(persistence("off"), Ok(Persistence::Off));
+        assert!(persistence("blend:1").is_err());
+        assert!(persistence("or:0").is_err());
+        assert!(persistence("ghost").is_err());
+    }
+
+    #[test]
+    fn test_upscaler() {
+        assert_eq!(parse(&["--upscale", "epx"]).unwrap().upscaler, Upscaler::Scale2x);
+        assert_eq!(parse(&["--upscale", "xbr"]).unwrap().upscaler, Upscaler::Xbr);
+        assert_eq!(Upscaler::Scale3x.factor(), 3);
+        assert!(parse(&["--upscale", "hq4x"]).is_err());
+    }
+
+    #[test]
+    fn test_crt() {
+        let config = parse(&["--crt", "--crt-scanlines", "1", "--crt-curvature", "0"]).unwrap();
+        assert!(config.crt);
+        assert_eq!(config.crt_settings.scanlines, 1.0);
+        assert_eq!(config.crt_settings.curvature, 0.0);
+        assert_eq!(config.crt_settings.bloom, CrtSettings::default().bloom);
+        assert!(parse(&["--crt-bloom", "2"]).is_err());
+        assert!(parse(&["--crt-vignette", "lots"]).is_err());
+        let config = parse(&["--shader-dir", "shaders"]).unwrap();
+        assert_eq!(config.shader_dir, Some(PathBuf::from("shaders")));
+    }
+
+    #[test]
+    fn test_capture() {
+        let config = parse(&["--capture-dir", "shots", "--capture-scale", "2", "--record-format", "apng"]).unwrap();
+        assert_eq!(config.capture_dir, PathBuf::from("shots"));
+        assert_eq!(config.capture_scale, 2);
+        assert_eq!(config.record_format, RecordFormat::Apng);
+        assert_eq!(config.frontend, Frontend::Window);
+        assert!(parse(&["--capture-scale", "0"]).is_err());
+        assert!(parse(&["--record-format", "mp4"]).is_err());
+        let config = parse(&["--screenshot", "pong.png", "--frames", "120", "pong.ch8"]).unwrap();
+        assert_eq!(config.frontend, Frontend::Headless);
+        assert_eq!(config.screenshot, Some(PathBuf::from("pong.png")));
+        assert_eq!(config.screenshot_frames, 120);
+    }
+
+    #[test]
+    fn test_terminal_with_rom() {
+        let config = parse(&["--terminal", "--braille", "pong.ch8"]).unwrap();
+        assert_eq!(config.frontend, Frontend::Terminal);
+        assert_eq!(config.terminal_glyphs, TerminalGlyphs::Braille);
+        assert_eq!(config.rom, Some(PathBuf::from("pong.ch8")));
+    }
+
+    #[test]
+    fn test_variant_and_rpl() {
+        let config = parse(&["--variant", "schip", "game.ch8"]).unwrap();
+        assert_eq!(config.platform.variant, Variant::SuperChip);
+        assert_eq!(config.rpl_path(), Some(PathBuf::from("game.rpl")));
+        let config = parse(&["--rpl", "flags.bin", "game.ch8"]).unwrap();
+        assert_eq!(config.rpl_path(), Some(PathBuf::from("flags.bin")));
+    }
+
+    #[test]
+    fn test_platform() {
+        let config = parse(&["--platform", "eti660", "--variant", "schip"]).unwrap();
+        assert_eq!(config.platform.program_start, MemoryAddress(0x600));
+        assert_eq!(config.platform.variant, Variant::SuperChip);
+        let config = parse(&["--program-start", "0x400", "--memory-size", "8192", "--font-start", "80"]).unwrap();
+        assert_eq!(config.platform.program_start, MemoryAddress(0x400));
+        assert_eq!(config.platform.font_start, MemoryAddress(80));
+        assert_eq!(config.platform.big_font_start, MemoryAddress(160));
+        assert_eq!(config.platform.memory_size, 8192);
+        assert!(parse(&["--memory-size", "5000"]).is_err());
+        let config = parse(&["--font", "vip", "--platform", "eti660"]).unwrap();
+        assert_eq!(config.platform.font, Font::vip());
+        assert!(parse(&["--program-start", "0xZZ"]).is_err());
+        assert!(parse(&["--font-start", "0x1C0"]).is_err());
+        assert!(parse(&["--program-start", "0x2000"]).is_err());
+        assert!(parse(&["--memory-size", "256"]).is_err());
+    }
+
+    #[test]
+    fn test_font_start() {
+        // V0 = 7, FX29
+        let program = [0x60, 0x07, 0xF0, 0x29];
+        let mut chip8 = parse(&["--font-start", "0x50"]).unwrap().create_chip8(&program).unwrap();
+        chip8.step();
+        chip8.step();
+        assert_eq!(chip8.i(), MemoryAddress(0x50 + 7 * 5));
+        assert_eq!(chip8.memory().read_bytes(chip8.i(), 5), &Font::default().small[35..40]);
+        let big_font_start = chip8.platform().big_font_start;
+        assert_eq!(chip8.memory().read_bytes(big_font_start, 10), &Font::default().big[..10]);
+    }
+
+    #[test]
+    fn test_vip_images() {
+        let config = parse(&["--vip-monitor", "vip.rom", "--vip-interpreter", "chip8.bin"]).unwrap();
+        assert_eq!(config.vip_monitor, Some(PathBuf::from("vip.rom")));
+        assert_eq!(config.vip_interpreter, Some(PathBuf::from("chip8.bin")));
+        assert!(parse(&["--vip-monitor", "vip.rom"]).is_err());
+        let vip = ["--vip-monitor", "vip.rom", "--vip-interpreter", "chip8.bin"];
+        assert!(parse(&[&vip[..], &["--variant", "schip"]].concat()).is_err());
+        assert!(parse(&[&vip[..], &["--font-start", "0x50"]].concat()).is_err());
+        assert!(parse(&[&vip[..], &["--font-file", "font.bin"]].concat()).is_err());
+    }
+
+    #[test]
+    fn test_rejects_unknown_options() {
+        assert!(parse(&["--bogus"]).is_err());
+        assert!(parse(&["--variant"]).is_err());
+        assert!(parse(&["--variant", "nope"]).is_err());
+        assert!(parse(&["a.ch8", "b.ch8"]).is_err());
+    }
+}
@@ -0,0 +1,187 @@
+use crate::chip8::{Chip8, MemoryAddress, Registers};
+use winit::{event::WindowEvent, window::Window};
+
+const MEMORY_BYTES_PER_ROW: usize = 16;
+const DISASSEMBLY_CONTEXT: u32 = 8;
+
+// Controls the debug overlay hands back to the App after each frame
+#[derive(Debug, Default, Copy, Clone)]
+pub struct DebugControls {
+    pub paused: bool,
+    pub step: bool,
+}
+
+// An egui overlay drawn on top of the Chip8 display in the same render pass.
+pub struct DebugUi {
+    context: egui::Context,
+    state: egui_winit::State,
+    renderer: egui_wgpu::Renderer,
+    paint_jobs: Vec<egui::ClippedPrimitive>,
+    textures_delta: egui::TexturesDelta,
+    visible: bool,
+}
+
+impl DebugUi {
+    pub fn new(window: &Window, device: &wgpu::Device, output_format: wgpu::TextureFormat) -> Self {
+        let context = egui::Context::default();
+        let mut state = egui_winit::State::new(window);
+        state.set_pixels_per_point(window.scale_factor() as f32);
+        let renderer = egui_wgpu::Renderer::new(device, output_format, None, 1);
+
+        Self {
+            context,
+            state,
+            renderer,
+            paint_jobs: Vec::new(),
+            textures_delta: egui::TexturesDelta::default(),
+            visible: false,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    pub fn visible(&self) -> bool {
+        self.visible
+    }
+
+    // returns a bool to indicate whether the overlay consumed the event. Events aren't passed on
+    // while the overlay is hidden, since its input is only taken while it's shown.
+    pub fn on_event(&mut self, event: &WindowEvent) -> bool {
+        self.visible && self.state.on_event(&self.context, event).consumed
+    }
+
+    // Runs the UI for this frame, recording any pause/step requests into controls
+    pub fn update(&mut self, window: &Window, chip8: &Chip8, controls: &mut DebugControls) {
+        if !self.visible {
+            self.paint_jobs.clear();
+            return;
+        }
+
+        let raw_input = self.state.take_egui_input(window);
+        let full_output = self.context.run(raw_input, |ctx| {
+            execution_window(ctx, chip8, controls);
+            // The VIP's own interpreter runs the program, so there's no CHIP-8 state to show
+            if chip8.vip().is_none() {
+                registers_window(ctx, chip8);
+                stack_window(ctx, chip8);
+                disassembly_window(ctx, chip8);
+                memory_window(ctx, chip8);
+            }
+        });
+        self.state.handle_platform_output(window, &self.context, full_output.platform_output);
+        self.paint_jobs = self.context.tessellate(full_output.shapes);
+        self.textures_delta.append(full_output.textures_delta);
+    }
+
+    // Uploads textures and vertex data, must be called before the render pass begins
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        screen_descriptor: &egui_wgpu::renderer::ScreenDescriptor,
+    ) {
+        for (id, image_delta) in &self.textures_delta.set {
+            self.renderer.update_texture(device, queue, *id, image_delta);
+        }
+        self.renderer.update_buffers(device, queue, encoder, &self.paint_jobs, screen_descriptor);
+    }
+
+    pub fn render<'rp>(
+        &'rp self,
+        render_pass: &mut wgpu::RenderPass<'rp>,
+        screen_descriptor: &egui_wgpu::renderer::ScreenDescriptor,
+    ) {
+        self.renderer.render(render_pass, &self.paint_jobs, screen_descriptor);
+    }
+
+    // Textures can only be freed once the frame using them has been submitted
+    pub fn finish_frame(&mut self) {
+        let textures_delta = std::mem::take(&mut self.textures_delta);
+        for id in &textures_delta.free {
+            self.renderer.free_texture(id);
+        }
+    }
+}
+
+fn execution_window(ctx: &egui::Context, chip8: &Chip8, controls: &mut DebugControls) {
+    egui::Window::new("Execution").show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            let label = if controls.paused { "Resume" } else { "Pause" };
+            if ui.button(label).clicked() {
+                controls.paused = !controls.paused;
+            }
+            if ui.add_enabled(controls.paused, egui::Button::new("Step")).clicked() {
+                controls.step = true;
+            }
+        });
+        match chip8.vip() {
+            Some(vip) => ui.monospace(format!("1802 PC: {:04X}  (running on the VIP)", vip.pc())),
+            None => ui.monospace(format!("PC: {:03X}  I: {:03X}", chip8.pc().0, chip8.i().0)),
+        };
+        ui.monospace(if chip8.sound_on() { "Sound: on" } else { "Sound: off" });
+    });
+}
+
+fn registers_window(ctx: &egui::Context, chip8: &Chip8) {
+    let registers = chip8.registers();
+    egui::Window::new("Registers").show(ctx, |ui| {
+        egui::Grid::new("registers_grid").striped(true).show(ui, |ui| {
+            for row in 0..4u8 {
+                for column in 0..4u8 {
+                    let vx = row * 4 + column;
+                    ui.monospace(format!("V{:X}: {:02X}", vx, registers[vx]));
+                }
+                ui.end_row();
+            }
+        });
+        ui.separator();
+        ui.monospace(format!("DT: {:02X}  ST: {:02X}", registers[Registers::DT], registers[Registers::ST]));
+    });
+}
+
+fn stack_window(ctx: &egui::Context, chip8: &Chip8) {
+    egui::Window::new("Stack").show(ctx, |ui| {
+        let stack = chip8.stack();
+        if stack.is_empty() {
+            ui.monospace("(empty)");
+        }
+        for (depth, addr) in stack.iter().enumerate().rev() {
+            ui.monospace(format!("{:X}: {:03X}", depth, addr.0));
+        }
+    });
+}
+
+fn disassembly_window(ctx: &egui::Context, chip8: &Chip8) {
+    egui::Window::new("Disassembly").show(ctx, |ui| {
+        let pc = chip8.pc().0;
+        let start = pc.saturating_sub(DISASSEMBLY_CONTEXT * 2);
+        for addr in (start..start + DISASSEMBLY_CONTEXT * 4).step_by(2) {
+            let text = format!("{:03X}  {}", addr, chip8.disassemble(MemoryAddress(addr)));
+            let text = egui::RichText::new(text).monospace();
+            if addr == pc {
+                ui.label(text.strong().background_color(ui.visuals().selection.bg_fill));
+            } else {
+                ui.label(text);
+            }
+        }
+    });
+}
+
+fn memory_window(ctx: &egui::Context, chip8: &Chip8) {
+    egui::Window::new("Memory").default_open(false).show(ctx, |ui| {
+        let memory = chip8.memory();
+        let num_rows = memory.size() / MEMORY_BYTES_PER_ROW;
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+        egui::ScrollArea::vertical().max_height(240.0).show_rows(ui, row_height, num_rows, |ui, rows| {
+            for row in rows {
+                let addr = MemoryAddress((row * MEMORY_BYTES_PER_ROW) as u32);
+                let bytes = memory.read_bytes(addr, MEMORY_BYTES_PER_ROW);
+                let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+                ui.monospace(format!("{:03X}: {}", addr.0, hex.join(" ")));
+            }
+        });
+    });
+}
@@ -1,369 +1,219 @@
+mod capture;
 mod chip8;
+mod config;
+mod debug_ui;
+mod palette;
+mod renderer;
+mod terminal;
+mod timing;
 mod util;
+mod vip;
 
-use chip8::Chip8;
+pub use capture::run_headless;
+pub use chip8::{Chip8, Framebuffer, Memory, MemoryAddress, Registers, SysContext, SysHandler};
+pub use config::{
+    Config, CrtSettings, Frontend, GpuBackend, Persistence, RecordFormat, RendererKind, ScaleMode, Upscaler,
+    USAGE,
+};
+pub use palette::{Palette, Theme};
+pub use renderer::{FrameBlender, RenderError, StartupError, WgpuRenderer};
+pub use terminal::run_terminal;
+
+use capture::Capture;
+use debug_ui::DebugControls;
+use renderer::{create_renderer, frame_blender, Renderer};
+use std::ops::Range;
+use std::time::Instant;
+use timing::{FrameClock, INSTRUCTIONS_PER_FRAME};
 use winit::{
     event::*,
     event_loop::EventLoop,
-    window::{WindowBuilder, Window},
+    window::{Fullscreen, WindowBuilder, Window},
 };
-use wgpu::util::DeviceExt;
-
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-struct Vertex {
-    position: [f32; 3],
-    tex_coords: [f32; 2],
-}
-
-impl Vertex {
-    const ATTRIBS: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2];
-
-    fn desc() -> wgpu::VertexBufferLayout<'static> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &Self::ATTRIBS,
-        }
-    }
-}
-
-const VERTICES: &[Vertex] = &[
-    Vertex { position: [-0.8, 0.4, 0.0], tex_coords: [0.0, 0.0] },
-    Vertex { position: [-0.8, -0.4, 0.0], tex_coords: [0.0, 1.0] },
-    Vertex { position: [0.8, -0.4, 0.0], tex_coords: [1.0, 1.0] },
-    Vertex { position: [0.8, 0.4, 0.0], tex_coords: [1.0, 0.0] },
-];
-
-const INDICES: &[u16] = &[
-    0, 1, 3,
-    1, 2, 3,
-];
 
 struct App {
+    // True when a blended frame hasn't been drawn yet. Fading changes the frame even when the
+    // screen stays the same.
+    blend_changed: bool,
+    capture: Capture,
     chip8: Chip8,
-    device: wgpu::Device,
-    diffuse_bind_group: wgpu::BindGroup,
-    diffuse_texture: wgpu::Texture,
-    index_buffer: wgpu::Buffer,
-    num_indices: u32,
-    queue: wgpu::Queue,
-    render_pipeline: wgpu::RenderPipeline,
-    size: winit::dpi::PhysicalSize<u32>,
-    surface: wgpu::Surface,
-    surface_config: wgpu::SurfaceConfiguration,
-    texture_size: wgpu::Extent3d,
-    vertex_buffer: wgpu::Buffer,
+    clock: FrameClock,
+    debug_controls: DebugControls,
+    // Rows of the screen that changed since the last redraw
+    dirty_rows: Option<Range<usize>>,
+    // Advanced once per emulated frame, the renderers draw the frame it keeps
+    frame_blender: Option<FrameBlender>,
+    // The colors being drawn with, kept for captures
+    palette: Palette,
+    // Dropped before the window it draws to
+    renderer: Box<dyn Renderer>,
+    theme: Theme,
     window: Window,
 }
 
 impl App {
-    async fn new(window: Window) -> Self {
-        let ibm_splashscreen = include_bytes!("../roms/2-ibm-logo.ch8");
-        let mut chip8 = Chip8::new();
-        chip8.load_program(&ibm_splashscreen[..]);
-        // Instance is the first thing we create with wgpu, it is used to create Adapters and
-        // Surfaces
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(),
-            dx12_shader_compiler: Default::default(),
-        });
+    async fn new(window: Window, chip8: Chip8, config: &Config) -> Result<Self, StartupError> {
+        let renderer = create_renderer(config, &window, &chip8).await?;
+        Ok(Self {
+            blend_changed: false,
+            capture: Capture::new(config),
+            chip8,
+            clock: FrameClock::new(),
+            debug_controls: DebugControls::default(),
+            dirty_rows: None,
+            frame_blender: frame_blender(config),
+            palette: config.palette,
+            renderer,
+            theme: config.theme,
+            window,
+        })
+    }
 
-        // Safety: The surface needs to live as long as the window that created it.
-        // The App owns the window so this should be safe.
-        // The surface is provided by the windowing library and is used to draw directly to the
-        // screen. It is also necessary to request an Adapter because the Adapter must be capable
-        // of supporting this surface
-        let surface = unsafe { instance.create_surface(&window) }.unwrap();
+    pub fn window(&self) -> &Window {
+        &self.window
+    }
 
-        // The Adapter is handle to a GPU. We'll only need it temporarily to create a Device and a
-        // Queue
-        let adapter = instance.request_adapter(
-            &wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false, // This disables software rendering fallback
-            }
-        ).await.unwrap();
+    fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        self.renderer.resize(new_size);
+    }
 
-        let (device, queue) = adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                features: wgpu::Features::empty(),
-                limits: wgpu::Limits::default(),
-                label: None,
+    // returns a bool to indicate whether an event has been fully processed
+    fn input(&mut self, event: &WindowEvent) -> bool {
+        if let WindowEvent::KeyboardInput {
+            input: KeyboardInput {
+                state: ElementState::Pressed,
+                virtual_keycode: Some(key),
+                ..
             },
-            None // Trace path
-        ).await.unwrap();
-
-        let surface_capabilities = surface.get_capabilities(&adapter);
-        // The shader code used here assumes an sRGB surface texture. Deviating from that will
-        // result in all colors coming out darker.
-        let surface_format = surface_capabilities.formats.iter()
-            .copied()
-            .find(|f| f.is_srgb())
-            .unwrap_or(surface_capabilities.formats[0]);
-        let size = window.inner_size();
-        let surface_config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT, // Textures will be written to screen
-            format: surface_format, // Format of how SurfaceTextures are stored on GPU
-            width: size.width, // width of the SurfaceTexture in pixels (usually same as window)
-            height: size.height, // height of the SurfaceTexture in pixels (usually same as window)
-            present_mode: surface_capabilities.present_modes[0], // PresentMode::Fifo is
-                                                                 // essentially vsync
-            alpha_mode: surface_capabilities.alpha_modes[0],
-            view_formats: vec![],
-        };
-        surface.configure(&device, &surface_config);
-
-        // TODO: replace magic constants
-        let texture_size = wgpu::Extent3d {
-            width: 64,
-            height: 32,
-            depth_or_array_layers: 1,
-        };
-
-        let diffuse_texture = device.create_texture(
-            &wgpu::TextureDescriptor {
-                size: texture_size,
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Rgba8UnormSrgb,
-                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-                label: Some("Chip8 display texture"),
-                view_formats: &[],
+            ..
+        } = event {
+            if self.hotkey(*key) {
+                return true;
             }
-        );
-
-        let diffuse_texture_view = diffuse_texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let diffuse_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        });
-
-        let texture_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        multisampled: false,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count: None,
-                },
-            ],
-            label: Some("texture_bind_group_layout"),
-        });
-
-        let diffuse_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_sampler),
-                }
-            ],
-            label: Some("diffuse_bind_group"),
-        });
-
-        let shader = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
-
-        let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Render Pipeline Layout"),
-            bind_group_layouts: &[&texture_bind_group_layout],
-            push_constant_ranges: &[],
-        });
+        }
+        self.renderer.on_event(event)
+    }
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[
-                    Vertex::desc(),
-                ],
-            },
-            // fragment shader is technically optional which is why it is wrapped in an Option
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: surface_config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false
+    // Runs whatever `key` is bound to, returning false if it isn't a hotkey
+    fn hotkey(&mut self, key: VirtualKeyCode) -> bool {
+        match key {
+            VirtualKeyCode::F1 => self.renderer.toggle_debug_ui(),
+            VirtualKeyCode::F2 => self.next_theme(),
+            VirtualKeyCode::F3 => {
+                self.renderer.toggle_crt();
+                self.window.request_redraw();
             },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-        });
-
-        let vertex_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex buffer"),
-                contents: bytemuck::cast_slice(VERTICES),
-                usage: wgpu::BufferUsages::VERTEX,
-        });
-
-        let index_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Index buffer"),
-                contents: bytemuck::cast_slice(INDICES),
-                usage: wgpu::BufferUsages::INDEX,
-            });
-        let num_indices = INDICES.len() as u32;
-
-        Self {
-            chip8,
-            device,
-            diffuse_bind_group,
-            diffuse_texture,
-            index_buffer,
-            num_indices,
-            queue,
-            render_pipeline,
-            size,
-            surface,
-            surface_config,
-            texture_size,
-            vertex_buffer,
-            window,
+            VirtualKeyCode::F9 => self.screenshot(),
+            VirtualKeyCode::F10 => self.toggle_recording(),
+            VirtualKeyCode::F11 => self.toggle_fullscreen(),
+            _ => return false,
         }
+        true
     }
 
-    pub fn window(&self) -> &Window {
-        &self.window
+    // Borderless on the current monitor, resizing the window redraws it
+    fn toggle_fullscreen(&mut self) {
+        let fullscreen = match self.window.fullscreen() {
+            Some(_) => None,
+            None => Some(Fullscreen::Borderless(None)),
+        };
+        self.window.set_fullscreen(fullscreen);
     }
 
-    fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
-        if new_size.width > 0 && new_size.height > 0 {
-            self.size = new_size;
-            self.surface_config.width = new_size.width;
-            self.surface_config.height = new_size.height;
-            self.surface.configure(&self.device, &self.surface_config);
+    // Replaces any colors given on the command line
+    fn next_theme(&mut self) {
+        self.theme = self.theme.next();
+        log::info!("Switched to the {} theme", self.theme);
+        self.palette = self.theme.palette();
+        self.renderer.set_palette(self.palette);
+        // The earlier frames were blended in the old colors
+        if let Some(blender) = &mut self.frame_blender {
+            blender.reset();
+            blender.blend_display(&self.chip8, &self.palette);
         }
+        self.window.request_redraw();
     }
 
-    // returns a bool to indicate whether an event has been fully processed
-    fn input(&mut self, _event: &WindowEvent) -> bool {
-        false
+    fn screenshot(&self) {
+        match self.capture.screenshot(&self.chip8, &self.palette, self.frame_blender.as_ref()) {
+            Ok(path) => log::info!("Saved a screenshot to {}", path.display()),
+            Err(err) => log::error!("Unable to save a screenshot: {}", err),
+        }
     }
 
-    fn update(&mut self) {
-        let mut display_pixels: [u8; 64*32*4] = [0; 64*32*4];
-        for byte in 0..self.chip8.display.len() {
-            let pixels = self.chip8.display[byte];
-            for bit in (0..8).rev() {
-                let color = if (pixels >> bit) & 1 == 1 { 255 } else { 0 };
-                display_pixels[(byte*8*4)+(7-bit)*4] = color;
-                display_pixels[(byte*8*4)+(7-bit)*4+1] = color;
-                display_pixels[(byte*8*4)+(7-bit)*4+2] = color;
-                display_pixels[(byte*8*4)+(7-bit)*4+3] = 255;
-            }
+    fn toggle_recording(&mut self) {
+        let recording = self.capture.recording();
+        match self.capture.toggle_recording(&self.chip8, &self.palette, self.frame_blender.as_ref()) {
+            Ok(path) if recording => log::info!("Saved the recording to {}", path.display()),
+            Ok(path) => log::info!("Recording to {}, F10 stops", path.display()),
+            Err(err) => log::error!("Unable to record: {}", err),
         }
-
-        self.queue.write_texture(
-            wgpu::ImageCopyTexture {
-                texture: &self.diffuse_texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            &display_pixels,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * 64u32),
-                rows_per_image: Some(32u32),
-            },
-            self.texture_size,
-        );
-        self.chip8.step();
     }
 
-    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
-        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
-        // builds a command buffer we can use to send commands to the GPU
-        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Render Encoder"),
-        });
-
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.3,
-                            g: 0.3,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: None,
+    fn update(&mut self) {
+        if self.debug_controls.paused {
+            if self.debug_controls.step {
+                self.chip8.step();
+                self.debug_controls.step = false;
+                // A step counts as a frame, otherwise its changes wouldn't show
+                self.blend();
+            }
+        } else {
+            for _ in 0..self.clock.frames_due(Instant::now()) {
+                self.chip8.run_frame(INSTRUCTIONS_PER_FRAME);
+                self.blend();
+                self.capture.frame(&self.chip8, &self.palette, self.frame_blender.as_ref());
+            }
+        }
+        if let Some(rows) = self.chip8.take_dirty_rows() {
+            self.dirty_rows = Some(match self.dirty_rows.take() {
+                Some(dirty) => dirty.start.min(rows.start)..dirty.end.max(rows.end),
+                None => rows,
             });
+        }
+    }
 
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+    fn blend(&mut self) {
+        if let Some(blender) = &mut self.frame_blender {
+            let was_settled = blender.settled();
+            blender.blend_display(&self.chip8, &self.palette);
+            self.blend_changed |= !(was_settled && blender.settled());
         }
+    }
 
-        self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
+    fn needs_redraw(&self) -> bool {
+        self.dirty_rows.is_some() || self.blend_changed || self.renderer.needs_redraw()
+    }
 
-        Ok(())
+    fn render(&mut self) -> Result<(), RenderError> {
+        let dirty_rows = self.dirty_rows.take();
+        self.blend_changed = false;
+        self.renderer.render(
+            &self.window,
+            &self.chip8,
+            dirty_rows,
+            self.frame_blender.as_ref(),
+            &mut self.debug_controls,
+        )
     }
 }
 
-pub async fn run() {
+// Only returns if the window can't be drawn to
+pub async fn run(config: Config, chip8: Chip8) -> Result<(), StartupError> {
     env_logger::init();
     let event_loop = EventLoop::new();
-    let window = WindowBuilder::new().build(&event_loop).unwrap();
-    let mut app = App::new(window).await;
+    let window = WindowBuilder::new()
+        .with_fullscreen(config.fullscreen.then_some(Fullscreen::Borderless(None)))
+        .build(&event_loop)
+        .map_err(StartupError::Window)?;
+    let mut app = App::new(window, chip8, &config).await?;
 
     event_loop.run(move |event, _, control_flow| {
         match event {
             Event::WindowEvent {
                 ref event,
                 window_id,
-            } if window_id == app.window().id() => if !app.input(event) {
+            } if window_id == app.window().id() && !app.input(event) => {
                 match event {
                     WindowEvent::CloseRequested |
                     WindowEvent::KeyboardInput {
@@ -374,25 +224,43 @@ pub async fn run() {
                         },
                         ..
                     } => control_flow.set_exit(),
-                    WindowEvent::Resized(physical_size) => app.resize(*physical_size),
-                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => app.resize(**new_inner_size),
+                    WindowEvent::Resized(physical_size) => {
+                        app.resize(*physical_size);
+                        app.window().request_redraw();
+                    },
+                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                        app.resize(**new_inner_size);
+                        app.window().request_redraw();
+                    },
                     _ => {},
                 }
             },
             Event::RedrawRequested(_) => {
-                app.update();
                 match app.render() {
                     Ok(_) => {},
                     // Reconfigure the surface if lost
-                    Err(wgpu::SurfaceError::Lost) => app.resize(app.size),
+                    Err(RenderError::Lost) => {
+                        app.resize(app.window().inner_size());
+                        app.window().request_redraw();
+                    },
                     // OOM (is this on the GPU side, OS side, or both?)
-                    Err(wgpu::SurfaceError::OutOfMemory) => control_flow.set_exit(),
+                    Err(RenderError::OutOfMemory) => control_flow.set_exit(),
                     // All other errors should be resolved by the next frame
-                    Err(e) => eprintln!("{:?}", e),
+                    Err(e) => eprintln!("{}", e),
                 }
             },
+            // Runs whatever frames are due, redraws only if something changed and then sleeps
+            // until the next frame
             Event::MainEventsCleared => {
-                app.window().request_redraw();
+                app.update();
+                if app.chip8.exited() {
+                    control_flow.set_exit();
+                } else {
+                    if app.needs_redraw() {
+                        app.window().request_redraw();
+                    }
+                    control_flow.set_wait_until(app.clock.next_frame());
+                }
             },
             _ => {}
         }
@@ -1,5 +1,35 @@
-use wgpuchip8::run; // Q: how do I express _this crate_ ?
+use wgpuchip8::{run, run_headless, run_terminal, Config, Frontend, USAGE}; // Q: how do I express _this crate_ ?
 
 fn main() {
-    pollster::block_on(run());
+    let config = Config::from_args(std::env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{}\n\n{}", err, USAGE);
+        std::process::exit(2);
+    });
+    if config.help {
+        println!("{}", USAGE);
+        return;
+    }
+    let program = config.load_rom().unwrap_or_else(|err| {
+        eprintln!("Unable to read ROM: {}", err);
+        std::process::exit(1);
+    });
+    let chip8 = config.create_chip8(&program).unwrap_or_else(|err| {
+        eprintln!("Unable to set up the machine: {}", err);
+        std::process::exit(1);
+    });
+
+    match config.frontend {
+        Frontend::Window => if let Err(err) = pollster::block_on(run(config, chip8)) {
+            eprintln!("Window error: {}", err);
+            std::process::exit(1);
+        },
+        Frontend::Terminal => if let Err(err) = run_terminal(config, chip8) {
+            eprintln!("Terminal error: {}", err);
+            std::process::exit(1);
+        },
+        Frontend::Headless => if let Err(err) = run_headless(config, chip8) {
+            eprintln!("Unable to save the screenshot: {}", err);
+            std::process::exit(1);
+        },
+    }
 }
@@ -0,0 +1,142 @@
+use std::fmt;
+use std::str::FromStr;
+
+// The colors the window is drawn with. Screens with colors of their own (the CHIP-8X color map
+// and MegaChip) only use the border.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Palette {
+    // RGBA indexed by a pixel's planes: background, plane 1 only, plane 2 only and both planes
+    pub colors: [[u8; 4]; 4],
+    // Fills the window around the display
+    pub border: [u8; 4],
+}
+
+impl Palette {
+    pub fn background(&self) -> [u8; 4] {
+        self.colors[0]
+    }
+
+    pub fn foreground(&self) -> [u8; 4] {
+        self.colors[1]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Theme::Classic.palette()
+    }
+}
+
+// Built in palettes, F2 cycles through them
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Theme {
+    // White on black with greys for the other planes
+    Classic,
+    Amber,
+    GreenPhosphor,
+    // Dark pixels on a greenish LCD
+    Lcd,
+    HighContrast,
+    // From the Okabe-Ito palette, the planes stay distinct with any kind of color blindness
+    ColorBlindSafe,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 6] = [
+        Theme::Classic,
+        Theme::Amber,
+        Theme::GreenPhosphor,
+        Theme::Lcd,
+        Theme::HighContrast,
+        Theme::ColorBlindSafe,
+    ];
+
+    pub fn palette(self) -> Palette {
+        let (colors, border) = match self {
+            Theme::Classic => ([0x000000, 0xFFFFFF, 0xAAAAAA, 0x555555], 0x000000),
+            Theme::Amber => ([0x1A1000, 0xFFB000, 0xA86A00, 0xFFDC8C], 0x0D0800),
+            Theme::GreenPhosphor => ([0x0A1A0A, 0x33FF66, 0x1F9940, 0xB3FFC6], 0x050D05),
+            Theme::Lcd => ([0x9BBC0F, 0x0F380F, 0x8BAC0F, 0x306230], 0x7A9A0C),
+            Theme::HighContrast => ([0x000000, 0xFFFFFF, 0xFFFF00, 0x00FFFF], 0x000000),
+            Theme::ColorBlindSafe => ([0x000000, 0xFFFFFF, 0x56B4E9, 0xE69F00], 0x000000),
+        };
+        Palette { colors: colors.map(rgba), border: rgba(border) }
+    }
+
+    // Wraps around to the first theme
+    pub fn next(self) -> Theme {
+        let index = Theme::ALL.iter().position(|&theme| theme == self).unwrap_or(0);
+        Theme::ALL[(index + 1) % Theme::ALL.len()]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Theme::Classic => "classic",
+            Theme::Amber => "amber",
+            Theme::GreenPhosphor => "green",
+            Theme::Lcd => "lcd",
+            Theme::HighContrast => "high-contrast",
+            Theme::ColorBlindSafe => "color-blind",
+        }
+    }
+}
+
+impl fmt::Display for Theme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Theme {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Theme::ALL.iter()
+            .copied()
+            .find(|theme| theme.name() == name)
+            .ok_or_else(|| format!("unknown theme: {}", name))
+    }
+}
+
+fn rgba(rgb: u32) -> [u8; 4] {
+    let [_, r, g, b] = rgb.to_be_bytes();
+    [r, g, b, 0xFF]
+}
+
+// Parses RRGGBB, optionally starting with #
+pub fn parse_color(color: &str) -> Result<[u8; 4], String> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("colors are written RRGGBB: {}", color));
+    }
+    Ok(rgba(u32::from_str_radix(hex, 16).unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_themes() {
+        assert_eq!(Palette::default().foreground(), [0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(Theme::Amber.palette().background(), [0x1A, 0x10, 0x00, 0xFF]);
+        for theme in Theme::ALL {
+            assert_eq!(theme.name().parse(), Ok(theme));
+            let colors = theme.palette().colors;
+            // Every combination of planes has to be told apart
+            for (index, color) in colors.iter().enumerate() {
+                assert!(!colors[..index].contains(color), "{} repeats a color", theme);
+            }
+        }
+        assert!("sepia".parse::<Theme>().is_err());
+        assert_eq!(Theme::ColorBlindSafe.next(), Theme::Classic);
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("#FFB000"), Ok([0xFF, 0xB0, 0x00, 0xFF]));
+        assert_eq!(parse_color("0a1a0a"), Ok([0x0A, 0x1A, 0x0A, 0xFF]));
+        assert!(parse_color("FFF").is_err());
+        assert!(parse_color("GGGGGG").is_err());
+    }
+}
@@ -0,0 +1,224 @@
+mod crt;
+mod gpu;
+mod persistence;
+mod post;
+mod software;
+mod upscale;
+
+pub use gpu::WgpuRenderer;
+pub use persistence::FrameBlender;
+pub use software::SoftwareRenderer;
+pub use upscale::upscale;
+
+use crate::chip8::{Chip8, MEGACHIP_HEIGHT, MEGACHIP_WIDTH};
+use crate::config::{Config, Persistence, RendererKind, ScaleMode};
+use crate::debug_ui::DebugControls;
+use crate::palette::Palette;
+use std::fmt;
+use std::ops::Range;
+use winit::{dpi::PhysicalSize, event::WindowEvent, window::Window};
+
+// Draws the Chip8's screen to the window, along with the debug overlay if the renderer has one
+pub trait Renderer {
+    fn resize(&mut self, size: PhysicalSize<u32>);
+
+    // `dirty_rows` are the rows of the screen that changed since the last call, renderers that
+    // keep the screen around between frames only need to update those. With persistence on the
+    // blender's last frame is drawn instead of the screen.
+    fn render(
+        &mut self,
+        window: &Window,
+        chip8: &Chip8,
+        dirty_rows: Option<Range<usize>>,
+        blender: Option<&FrameBlender>,
+        controls: &mut DebugControls,
+    ) -> Result<(), RenderError>;
+
+    // True while the renderer wants to draw every frame even if the screen hasn't changed
+    fn needs_redraw(&self) -> bool {
+        false
+    }
+
+    // returns a bool to indicate whether the debug overlay consumed the event
+    fn on_event(&mut self, _event: &WindowEvent) -> bool {
+        false
+    }
+
+    fn toggle_debug_ui(&mut self) {}
+
+    // Takes effect on the next render
+    fn set_palette(&mut self, palette: Palette);
+
+    // Switches the CRT effect on or off, for renderers that have one
+    fn toggle_crt(&mut self) {}
+}
+
+#[derive(Debug)]
+pub enum RenderError {
+    // The surface has to be set up again, resizing it does that
+    Lost,
+    OutOfMemory,
+    // Anything else, rendering carries on with the next frame
+    Other(String),
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RenderError::Lost => write!(f, "surface lost"),
+            RenderError::OutOfMemory => write!(f, "out of memory"),
+            RenderError::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+// Why the window couldn't be set up for drawing
+#[derive(Debug)]
+pub enum StartupError {
+    Window(winit::error::OsError),
+    Surface(wgpu::CreateSurfaceError),
+    // Neither a hardware nor a fallback adapter supports the surface, or there's no adapter at all
+    NoAdapter,
+    Device(wgpu::RequestDeviceError),
+    // The adapter can't present to the surface in any format
+    Incompatible,
+    Software(softbuffer::SoftBufferError),
+}
+
+impl fmt::Display for StartupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StartupError::Window(err) => write!(f, "unable to open a window: {}", err),
+            StartupError::Surface(err) => write!(f, "unable to create a wgpu surface: {}", err),
+            StartupError::NoAdapter => write!(f, "no usable GPU adapter"),
+            StartupError::Device(err) => write!(f, "unable to open the GPU: {}", err),
+            StartupError::Incompatible => write!(f, "the GPU can't present to the window"),
+            StartupError::Software(err) => write!(f, "unable to start the software renderer: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for StartupError {}
+
+// Auto prefers the GPU and falls back to the software renderer when the GPU can't be used
+pub async fn create_renderer(config: &Config, window: &Window, chip8: &Chip8) -> Result<Box<dyn Renderer>, StartupError> {
+    let gpu = WgpuRenderer::new(config, window, chip8);
+    match config.renderer {
+        RendererKind::Wgpu => Ok(Box::new(gpu.await?)),
+        RendererKind::Software => Ok(Box::new(SoftwareRenderer::new(config, window)?)),
+        RendererKind::Auto => match gpu.await {
+            Ok(renderer) => Ok(Box::new(renderer)),
+            Err(err) => {
+                log::warn!("{}, falling back to the software renderer", err);
+                Ok(Box::new(SoftwareRenderer::new(config, window)?))
+            },
+        },
+    }
+}
+
+// None when persistence is off, so renderers can skip blending altogether
+pub fn frame_blender(config: &Config) -> Option<FrameBlender> {
+    (config.persistence != Persistence::Off).then(|| FrameBlender::new(config.persistence))
+}
+
+// A frame of whichever screen the program is drawing to, `screen_size` gives its size
+pub enum Screen<'a> {
+    // A byte of plane bits for each pixel, which the palette turns into colors
+    Indexed { planes: &'a [u8] },
+    // The CHIP-8X color map and MegaChip color each pixel themselves
+    Rgba { pixels: Vec<u8> },
+}
+
+fn screen_size(chip8: &Chip8) -> (usize, usize) {
+    match chip8.mega_display() {
+        Some(_) => (MEGACHIP_WIDTH, MEGACHIP_HEIGHT),
+        None => (chip8.framebuffer.width(), chip8.framebuffer.height()),
+    }
+}
+
+// The shaders output linear colors, the sRGB surface converts them back
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 { value / 12.92 } else { ((value + 0.055) / 1.055).powf(2.4) }
+}
+
+fn linear_color(color: [u8; 4]) -> [f32; 4] {
+    let [r, g, b, a] = color;
+    [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), a as f32 / 255.0]
+}
+
+// True when the screen has colors of its own and the palette only colors the border
+fn direct_color(chip8: &Chip8) -> bool {
+    chip8.mega_display().is_some() || chip8.color_map().is_some()
+}
+
+fn screen(chip8: &Chip8) -> Screen<'_> {
+    if direct_color(chip8) {
+        let (_, _, pixels) = display_rgba(chip8, &Palette::default());
+        return Screen::Rgba { pixels };
+    }
+    Screen::Indexed { planes: chip8.framebuffer.pixels() }
+}
+
+// The part of the window the display is drawn to, in pixels
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Viewport {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// Centers the display in the window. Integer scaling falls back to fitting when the window is
+// smaller than the display.
+fn viewport(scale: ScaleMode, display: (usize, usize), window: PhysicalSize<u32>) -> Viewport {
+    let (width, height) = (display.0.max(1) as u32, display.1.max(1) as u32);
+    let integer_scale = (window.width / width).min(window.height / height);
+    let (scaled_width, scaled_height) = match scale {
+        ScaleMode::Integer if integer_scale > 0 => (width * integer_scale, height * integer_scale),
+        ScaleMode::Integer | ScaleMode::Fit => {
+            let fit = (window.width as f32 / width as f32).min(window.height as f32 / height as f32);
+            ((width as f32 * fit).round() as u32, (height as f32 * fit).round() as u32)
+        },
+        ScaleMode::Stretch => (window.width, window.height),
+    };
+    let (scaled_width, scaled_height) = (scaled_width.clamp(1, window.width.max(1)), scaled_height.clamp(1, window.height.max(1)));
+    Viewport {
+        x: (window.width.saturating_sub(scaled_width)) / 2,
+        y: (window.height.saturating_sub(scaled_height)) / 2,
+        width: scaled_width,
+        height: scaled_height,
+    }
+}
+
+// Returns the width, height and RGBA pixels of whichever screen the program is drawing to
+pub fn display_rgba(chip8: &Chip8, palette: &Palette) -> (usize, usize, Vec<u8>) {
+    if let Some(mega_display) = chip8.mega_display() {
+        return (MEGACHIP_WIDTH, MEGACHIP_HEIGHT, mega_display.to_rgba());
+    }
+
+    let framebuffer = &chip8.framebuffer;
+    let pixels = match chip8.color_map() {
+        Some(color_map) => framebuffer.to_rgba_with(|x, y, planes| {
+            if planes != 0 { color_map.foreground(x, y) } else { color_map.background() }
+        }),
+        None => framebuffer.to_rgba(&palette.colors),
+    };
+    (framebuffer.width(), framebuffer.height(), pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_viewport() {
+        let window = PhysicalSize::new(800, 600);
+        let viewport = |scale| super::viewport(scale, (64, 32), window);
+        assert_eq!(viewport(ScaleMode::Integer), Viewport { x: 16, y: 108, width: 768, height: 384 });
+        assert_eq!(viewport(ScaleMode::Fit), Viewport { x: 0, y: 100, width: 800, height: 400 });
+        assert_eq!(viewport(ScaleMode::Stretch), Viewport { x: 0, y: 0, width: 800, height: 600 });
+        let tiny = super::viewport(ScaleMode::Integer, (128, 64), PhysicalSize::new(100, 100));
+        assert_eq!(tiny, Viewport { x: 0, y: 25, width: 100, height: 50 });
+    }
+}
@@ -0,0 +1,110 @@
+use super::linear_color;
+use super::post::{create_pipeline, uniform_bind_group, uniform_layout};
+use crate::config::CrtSettings;
+use wgpu::util::DeviceExt;
+
+// Matches the Crt struct in crt.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct CrtUniform {
+    source_size: [f32; 2],
+    scanlines: f32,
+    curvature: f32,
+    bloom: f32,
+    vignette: f32,
+    _padding: [f32; 2],
+    border: [f32; 4],
+}
+
+impl CrtUniform {
+    fn new(settings: &CrtSettings, source_size: (usize, usize), border: [u8; 4]) -> Self {
+        Self {
+            source_size: [source_size.0 as f32, source_size.1 as f32],
+            scanlines: settings.scanlines,
+            curvature: settings.curvature,
+            bloom: settings.bloom,
+            vignette: settings.vignette,
+            _padding: [0.0; 2],
+            border: linear_color(border),
+        }
+    }
+}
+
+// A pass that draws the display like a CRT monitor, with scanlines, curvature, bloom and a
+// vignette. It always runs last, after any user shaders.
+pub struct CrtPass {
+    pub enabled: bool,
+    pipeline: wgpu::RenderPipeline,
+    settings: CrtSettings,
+    uniform_bind_group: wgpu::BindGroup,
+    uniform_buffer: wgpu::Buffer,
+}
+
+impl CrtPass {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        input_layout: &wgpu::BindGroupLayout,
+        settings: CrtSettings,
+        enabled: bool,
+    ) -> Self {
+        let uniform_layout = uniform_layout(device);
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("CRT uniform buffer"),
+            contents: bytemuck::bytes_of(&CrtUniform::new(&settings, (1, 1), [0, 0, 0, 0xFF])),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let uniform_bind_group = uniform_bind_group(device, &uniform_layout, &uniform_buffer);
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("crt.wgsl"));
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("CRT pipeline layout"),
+            bind_group_layouts: &[input_layout, &uniform_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_pipeline(device, &layout, &shader, format);
+
+        Self {
+            enabled,
+            pipeline,
+            settings,
+            uniform_bind_group,
+            uniform_buffer,
+        }
+    }
+
+    pub fn prepare(&self, queue: &wgpu::Queue, source_size: (usize, usize), border: [u8; 4]) {
+        let uniform = CrtUniform::new(&self.settings, source_size, border);
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    // Draws `input` to the render pass's viewport
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, input: &'a wgpu::BindGroup) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, input, &[]);
+        render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crt_uniform() {
+        // WGSL aligns the border to 16 bytes
+        assert_eq!(std::mem::size_of::<CrtUniform>(), 48);
+        let uniform = CrtUniform::new(&CrtSettings::default(), (64, 32), [0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(uniform.source_size, [64.0, 32.0]);
+        assert_eq!(uniform.border, [1.0; 4]);
+    }
+
+    #[test]
+    fn test_shader() {
+        let module = naga::front::wgsl::parse_str(include_str!("crt.wgsl")).unwrap();
+        naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::empty())
+            .validate(&module)
+            .unwrap();
+    }
+}
@@ -0,0 +1,75 @@
+// Vertex shader
+
+struct VertexOutput {
+	@builtin(position) clip_position: vec4<f32>,
+	@location(0) tex_coords: vec2<f32>,
+};
+
+// A triangle covering the viewport, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	var out: VertexOutput;
+	out.tex_coords = uv;
+	out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+	return out;
+}
+
+// Fragment shader
+
+// Matches CrtUniform in crt.rs, the effects are off at 0 and strongest at 1
+struct Crt {
+	// Pixels in the emulated display, each row gets a scanline
+	source_size: vec2<f32>,
+	scanlines: f32,
+	curvature: f32,
+	bloom: f32,
+	vignette: f32,
+	// Linear color outside the curved screen
+	border: vec4<f32>,
+};
+
+// The previous pass's output
+@group(0) @binding(0)
+var t_scene: texture_2d<f32>;
+@group(0) @binding(1)
+var s_scene: sampler;
+@group(1) @binding(0)
+var<uniform> crt: Crt;
+
+// Bulges the screen out from its center like a tube's glass
+fn curve(uv: vec2<f32>) -> vec2<f32> {
+	let centered = uv * 2.0 - 1.0;
+	let bulge = centered.yx * centered.yx * crt.curvature * 0.25;
+	return (centered * (1.0 + bulge)) * 0.5 + 0.5;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	let uv = curve(in.tex_coords);
+	var color = textureSampleLevel(t_scene, s_scene, uv, 0.0).rgb;
+
+	// Bright pixels glow into their neighbours, up to a display pixel away
+	let texel = 1.0 / crt.source_size;
+	var glow = vec3<f32>(0.0);
+	for (var y = -2; y <= 2; y++) {
+		for (var x = -2; x <= 2; x++) {
+			let offset = vec2<f32>(f32(x), f32(y)) * texel * 0.5;
+			glow += textureSampleLevel(t_scene, s_scene, uv + offset, 0.0).rgb;
+		}
+	}
+	color += glow / 25.0 * crt.bloom;
+
+	// Darkest between the display's rows
+	let row = fract(uv.y * crt.source_size.y);
+	color *= mix(1.0, sin(row * 3.14159265), crt.scanlines);
+
+	// Darker towards the corners
+	let edges = uv * (1.0 - uv);
+	color *= mix(1.0, pow(clamp(edges.x * edges.y * 16.0, 0.0, 1.0), 0.25), crt.vignette);
+
+	if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) {
+		return crt.border;
+	}
+	return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,913 @@
+use super::crt::CrtPass;
+use super::post::{PostTargets, UserShaders};
+use super::upscale::UpscalePass;
+use super::{
+    direct_color, linear_color, screen, screen_size, viewport, FrameBlender,
+    RenderError, Renderer, Screen, StartupError, Viewport,
+};
+use crate::chip8::Chip8;
+use crate::config::{Config, GpuBackend, ScaleMode};
+use crate::debug_ui::{DebugControls, DebugUi};
+use crate::palette::Palette;
+use std::ops::Range;
+use wgpu::util::DeviceExt;
+use winit::{dpi::PhysicalSize, event::WindowEvent, window::Window};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Vertex {
+    position: [f32; 3],
+    tex_coords: [f32; 2],
+}
+
+impl Vertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+// Covers the whole viewport, which is set to wherever the display goes in the window
+const VERTICES: &[Vertex] = &[
+    Vertex { position: [-1.0, 1.0, 0.0], tex_coords: [0.0, 0.0] },
+    Vertex { position: [-1.0, -1.0, 0.0], tex_coords: [0.0, 1.0] },
+    Vertex { position: [1.0, -1.0, 0.0], tex_coords: [1.0, 1.0] },
+    Vertex { position: [1.0, 1.0, 0.0], tex_coords: [1.0, 0.0] },
+];
+
+const INDICES: &[u16] = &[
+    0, 1, 3,
+    1, 2, 3,
+];
+
+// Palette entries the shader has room for, enough for four planes
+const PALETTE_SIZE: usize = 16;
+
+// Matches the Display struct in shader.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct DisplayUniform {
+    palette: [[f32; 4]; PALETTE_SIZE],
+    direct_color: u32,
+    _padding: [u32; 3],
+}
+
+impl DisplayUniform {
+    fn new(palette: &[[u8; 4]], direct_color: bool) -> Self {
+        let mut uniform = Self { palette: [[0.0; 4]; PALETTE_SIZE], direct_color: direct_color as u32, _padding: [0; 3] };
+        for (entry, &color) in uniform.palette.iter_mut().zip(palette) {
+            *entry = linear_color(color);
+        }
+        uniform
+    }
+}
+
+fn clear_color(color: [u8; 4]) -> wgpu::Color {
+    let [r, g, b, a] = linear_color(color).map(|value| value as f64);
+    wgpu::Color { r, g, b, a }
+}
+
+// Where frames are drawn
+enum Target {
+    Window {
+        surface: wgpu::Surface,
+        config: wgpu::SurfaceConfiguration,
+    },
+    // A texture frames can be read back from
+    Offscreen(wgpu::Texture),
+}
+
+// A texture to draw a frame to, along with the window's surface texture to present afterwards
+struct Frame {
+    view: wgpu::TextureView,
+    output: Option<wgpu::SurfaceTexture>,
+}
+
+impl Target {
+    fn size(&self) -> PhysicalSize<u32> {
+        match self {
+            Target::Window { config, .. } => PhysicalSize::new(config.width, config.height),
+            Target::Offscreen(texture) => PhysicalSize::new(texture.width(), texture.height()),
+        }
+    }
+
+    fn format(&self) -> wgpu::TextureFormat {
+        match self {
+            Target::Window { config, .. } => config.format,
+            Target::Offscreen(texture) => texture.format(),
+        }
+    }
+
+    fn next_frame(&self) -> Result<Frame, RenderError> {
+        match self {
+            Target::Window { surface, .. } => {
+                let output = surface.get_current_texture().map_err(|err| match err {
+                    wgpu::SurfaceError::Lost => RenderError::Lost,
+                    wgpu::SurfaceError::OutOfMemory => RenderError::OutOfMemory,
+                    err => RenderError::Other(err.to_string()),
+                })?;
+                let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+                Ok(Frame { view, output: Some(output) })
+            },
+            Target::Offscreen(texture) => {
+                let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+                Ok(Frame { view, output: None })
+            },
+        }
+    }
+}
+
+// Draws the display as a textured quad, with the egui debug overlay on top
+pub struct WgpuRenderer {
+    crt: CrtPass,
+    // Only drawn in a window
+    debug_ui: Option<DebugUi>,
+    device: wgpu::Device,
+    diffuse_bind_group: wgpu::BindGroup,
+    // Used for screens with colors of their own
+    diffuse_texture: wgpu::Texture,
+    diffuse_sampler: wgpu::Sampler,
+    display_uniform_buffer: wgpu::Buffer,
+    // Each pixel's planes, which the shader looks up in the palette
+    planes_texture: wgpu::Texture,
+    post_targets: PostTargets,
+    index_buffer: wgpu::Buffer,
+    num_indices: u32,
+    palette: Palette,
+    queue: wgpu::Queue,
+    render_pipeline: wgpu::RenderPipeline,
+    scale: ScaleMode,
+    target: Target,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    texture_size: wgpu::Extent3d,
+    upscale: UpscalePass,
+    user_shaders: Option<UserShaders>,
+    vertex_buffer: wgpu::Buffer,
+}
+
+impl WgpuRenderer {
+    pub async fn new(config: &Config, window: &Window, chip8: &Chip8) -> Result<Self, StartupError> {
+        // Instance is the first thing we create with wgpu, it is used to create Adapters and
+        // Surfaces
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: backends(config.gpu_backend),
+            dx12_shader_compiler: Default::default(),
+        });
+
+        // Safety: The surface needs to live as long as the window that created it.
+        // The App owns the window and drops the renderer first so this should be safe.
+        // The surface is provided by the windowing library and is used to draw directly to the
+        // screen. It is also necessary to request an Adapter because the Adapter must be capable
+        // of supporting this surface
+        let surface = unsafe { instance.create_surface(window) }.map_err(StartupError::Surface)?;
+
+        let adapter = request_adapter(&instance, Some(&surface)).await?;
+        let (device, queue) = request_device(&adapter).await?;
+
+        let surface_capabilities = surface.get_capabilities(&adapter);
+        // The shader code used here assumes an sRGB surface texture. Deviating from that will
+        // result in all colors coming out darker.
+        let surface_format = surface_capabilities.formats.iter()
+            .copied()
+            .find(|f| f.is_srgb())
+            .or_else(|| surface_capabilities.formats.first().copied())
+            .ok_or(StartupError::Incompatible)?;
+        let size = window.inner_size();
+        let surface_config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT, // Textures will be written to screen
+            format: surface_format, // Format of how SurfaceTextures are stored on GPU
+            width: size.width, // width of the SurfaceTexture in pixels (usually same as window)
+            height: size.height, // height of the SurfaceTexture in pixels (usually same as window)
+            present_mode: present_mode(config.vsync, &surface_capabilities.present_modes),
+            alpha_mode: surface_capabilities.alpha_modes.first().copied().unwrap_or(wgpu::CompositeAlphaMode::Auto),
+            view_formats: vec![],
+        };
+        surface.configure(&device, &surface_config);
+
+        let debug_ui = DebugUi::new(window, &device, surface_config.format);
+        let target = Target::Window { surface, config: surface_config };
+        Ok(Self::with_target(config, chip8, device, queue, target, Some(debug_ui)))
+    }
+
+    // Draws to a `width` by `height` texture instead of a window, see `render_offscreen`
+    pub async fn offscreen(config: &Config, chip8: &Chip8, width: u32, height: u32) -> Result<Self, StartupError> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: backends(config.gpu_backend),
+            dx12_shader_compiler: Default::default(),
+        });
+        let adapter = request_adapter(&instance, None).await?;
+        let (device, queue) = request_device(&adapter).await?;
+        let target = Target::Offscreen(create_offscreen_texture(&device, width, height));
+        Ok(Self::with_target(config, chip8, device, queue, target, None))
+    }
+
+    fn with_target(
+        config: &Config,
+        chip8: &Chip8,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        target: Target,
+        debug_ui: Option<DebugUi>,
+    ) -> Self {
+        let format = target.format();
+        let diffuse_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let texture_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Uint,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("texture_bind_group_layout"),
+        });
+
+        let display_uniform_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Display uniform buffer"),
+                contents: bytemuck::bytes_of(&DisplayUniform::new(&[], false)),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+
+        let (diffuse_texture, planes_texture, diffuse_bind_group) = create_display_textures(
+            &device,
+            &texture_bind_group_layout,
+            &diffuse_sampler,
+            &display_uniform_buffer,
+            chip8.framebuffer.width(),
+            chip8.framebuffer.height(),
+        );
+        let texture_size = diffuse_texture.size();
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
+
+        let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Render Pipeline Layout"),
+            bind_group_layouts: &[&texture_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Render pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[
+                    Vertex::desc(),
+                ],
+            },
+            // fragment shader is technically optional which is why it is wrapped in an Option
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        });
+
+        let vertex_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Vertex buffer"),
+                contents: bytemuck::cast_slice(VERTICES),
+                usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let index_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Index buffer"),
+                contents: bytemuck::cast_slice(INDICES),
+                usage: wgpu::BufferUsages::INDEX,
+            });
+        let num_indices = INDICES.len() as u32;
+
+        let post_targets = PostTargets::new(&device, format);
+        let user_shaders = config.shader_dir.clone().map(|dir| {
+            UserShaders::new(&device, format, post_targets.layout(), dir)
+        });
+        let upscale = UpscalePass::new(&device, format, post_targets.layout(), config.upscaler);
+        let crt = CrtPass::new(&device, format, post_targets.layout(), config.crt_settings, config.crt);
+
+        Self {
+            crt,
+            debug_ui,
+            device,
+            diffuse_bind_group,
+            diffuse_sampler,
+            diffuse_texture,
+            display_uniform_buffer,
+            planes_texture,
+            post_targets,
+            index_buffer,
+            num_indices,
+            palette: config.palette,
+            queue,
+            render_pipeline,
+            scale: config.scale,
+            target,
+            texture_bind_group_layout,
+            texture_size,
+            upscale,
+            user_shaders,
+            vertex_buffer,
+        }
+    }
+
+    // Draws the whole screen, or the blender's last frame, to the offscreen texture and reads the
+    // frame back as it would appear in a window, in sRGB RGBA rows
+    pub fn render_offscreen(&mut self, chip8: &Chip8, blender: Option<&FrameBlender>) -> Result<Vec<u8>, RenderError> {
+        let frame = self.target.next_frame()?;
+        let (_, height) = screen_size(chip8);
+        self.draw(chip8, Some(0..height), blender, &frame.view, 1.0);
+        match &self.target {
+            Target::Offscreen(texture) => self.read_back(texture),
+            Target::Window { .. } => Err(RenderError::Other("only an offscreen renderer can be read back".to_string())),
+        }
+    }
+
+    fn read_back(&self, texture: &wgpu::Texture) -> Result<Vec<u8>, RenderError> {
+        let row_size = texture.width() * 4;
+        // Copies have to start each row on an aligned offset
+        let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_row_size = row_size.div_ceil(alignment) * alignment;
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback buffer"),
+            size: (padded_row_size * texture.height()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Readback encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_size),
+                    rows_per_image: Some(texture.height()),
+                },
+            },
+            texture.size(),
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv()
+            .map_err(|err| RenderError::Other(err.to_string()))?
+            .map_err(|err| RenderError::Other(err.to_string()))?;
+        let pixels = slice.get_mapped_range()
+            .chunks(padded_row_size as usize)
+            .flat_map(|row| &row[..row_size as usize])
+            .copied()
+            .collect();
+        buffer.unmap();
+        Ok(pixels)
+    }
+
+    // Uploads `rows` of a whole screen's worth of `data`
+    fn write_texture(&self, texture: &wgpu::Texture, data: &[u8], bytes_per_pixel: u32, rows: Range<usize>) {
+        let bytes_per_row = bytes_per_pixel * self.texture_size.width;
+        let rows = rows.start..rows.end.min(self.texture_size.height as usize);
+        if rows.is_empty() {
+            return;
+        }
+        self.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: 0, y: rows.start as u32, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            &data[rows.start * bytes_per_row as usize..rows.end * bytes_per_row as usize],
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: Some(rows.len() as u32),
+            },
+            wgpu::Extent3d {
+                width: self.texture_size.width,
+                height: rows.len() as u32,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
+
+impl Renderer for WgpuRenderer {
+    fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        if new_size.width == 0 || new_size.height == 0 {
+            return;
+        }
+        match &mut self.target {
+            Target::Window { surface, config } => {
+                config.width = new_size.width;
+                config.height = new_size.height;
+                surface.configure(&self.device, config);
+            },
+            Target::Offscreen(texture) => {
+                *texture = create_offscreen_texture(&self.device, new_size.width, new_size.height);
+            },
+        }
+    }
+
+    fn on_event(&mut self, event: &WindowEvent) -> bool {
+        self.debug_ui.as_mut().is_some_and(|debug_ui| debug_ui.on_event(event))
+    }
+
+    fn toggle_debug_ui(&mut self) {
+        if let Some(debug_ui) = &mut self.debug_ui {
+            debug_ui.toggle();
+        }
+    }
+
+    fn needs_redraw(&self) -> bool {
+        // User shaders can animate, and are checked for changes when drawn
+        self.debug_ui.as_ref().is_some_and(|debug_ui| debug_ui.visible()) || self.user_shaders.is_some()
+    }
+
+    fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    fn toggle_crt(&mut self) {
+        self.crt.enabled = !self.crt.enabled;
+    }
+
+    fn render(
+        &mut self,
+        window: &Window,
+        chip8: &Chip8,
+        dirty_rows: Option<Range<usize>>,
+        blender: Option<&FrameBlender>,
+        controls: &mut DebugControls,
+    ) -> Result<(), RenderError> {
+        if let Some(debug_ui) = &mut self.debug_ui {
+            debug_ui.update(window, chip8, controls);
+        }
+        let frame = self.target.next_frame()?;
+        self.draw(chip8, dirty_rows, blender, &frame.view, window.scale_factor() as f32);
+        if let Some(output) = frame.output {
+            output.present();
+        }
+        Ok(())
+    }
+}
+
+impl WgpuRenderer {
+    // Draws the display, any post-processing passes and the debug overlay to `view`
+    fn draw(
+        &mut self,
+        chip8: &Chip8,
+        mut dirty_rows: Option<Range<usize>>,
+        blender: Option<&FrameBlender>,
+        view: &wgpu::TextureView,
+        pixels_per_point: f32,
+    ) {
+        let (width, height) = screen_size(chip8);
+        if (width as u32, height as u32) != (self.texture_size.width, self.texture_size.height) {
+            (self.diffuse_texture, self.planes_texture, self.diffuse_bind_group) = create_display_textures(
+                &self.device,
+                &self.texture_bind_group_layout,
+                &self.diffuse_sampler,
+                &self.display_uniform_buffer,
+                width,
+                height,
+            );
+            self.texture_size = self.diffuse_texture.size();
+            dirty_rows = Some(0..height);
+        }
+
+        // Blended frames put every screen on the RGBA path
+        let blended = blender.and_then(FrameBlender::frame).filter(|&(w, h, _)| (w, h) == (width, height));
+        let direct_color = if let Some((_, _, pixels)) = blended {
+            // The history changes every frame, so the whole screen is uploaded
+            self.write_texture(&self.diffuse_texture, pixels, 4, 0..height);
+            true
+        } else {
+            if let Some(rows) = dirty_rows {
+                match screen(chip8) {
+                    Screen::Indexed { planes } => self.write_texture(&self.planes_texture, planes, 1, rows),
+                    Screen::Rgba { pixels } => self.write_texture(&self.diffuse_texture, &pixels, 4, rows),
+                }
+            }
+            direct_color(chip8)
+        };
+        // Small enough to write every time, which picks up palette changes
+        let uniform = DisplayUniform::new(&self.palette.colors, direct_color);
+        self.queue.write_buffer(&self.display_uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+
+        // builds a command buffer we can use to send commands to the GPU
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Render Encoder"),
+        });
+        let size = self.target.size();
+        let screen_descriptor = egui_wgpu::renderer::ScreenDescriptor {
+            size_in_pixels: [size.width, size.height],
+            pixels_per_point,
+        };
+        if let Some(debug_ui) = &mut self.debug_ui {
+            debug_ui.prepare(&self.device, &self.queue, &mut encoder, &screen_descriptor);
+        }
+
+        let viewport = viewport(self.scale, (width, height), size);
+        let border = clear_color(self.palette.border);
+
+        // The upscaler runs first and the CRT effect last, with user shaders in between. Each pass
+        // reads the previous one's output from a post target and draws to the other, or the window
+        // if it's last.
+        if let Some(user_shaders) = &mut self.user_shaders {
+            user_shaders.reload_changed(&self.device);
+            user_shaders.prepare(&self.queue, viewport, (width, height));
+        }
+        let user_passes = self.user_shaders.as_ref().map_or(0, |user_shaders| user_shaders.pass_count());
+        let post_passes: Vec<PostPass> = self.upscale.enabled().then_some(PostPass::Upscale).into_iter()
+            .chain((0..user_passes).map(PostPass::User))
+            .chain(self.crt.enabled.then_some(PostPass::Crt))
+            .collect();
+        if !post_passes.is_empty() {
+            self.post_targets.resize(&self.device, viewport);
+            self.upscale.prepare(&self.queue, (width, height));
+            self.crt.prepare(&self.queue, (width, height), self.palette.border);
+        }
+        let target_viewport = Viewport { x: 0, y: 0, ..viewport };
+
+        {
+            let (target, display_viewport) = if !post_passes.is_empty() {
+                (self.post_targets.view(0), target_viewport)
+            } else {
+                (view, viewport)
+            };
+            let mut render_pass = begin_render_pass(&mut encoder, target, border);
+            set_viewport(&mut render_pass, display_viewport);
+            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+            if let (true, Some(debug_ui)) = (post_passes.is_empty(), &self.debug_ui) {
+                debug_ui.render(&mut render_pass, &screen_descriptor);
+            }
+        }
+        for (index, pass) in post_passes.iter().enumerate() {
+            let last = index + 1 == post_passes.len();
+            let (target, pass_viewport) = if last {
+                (view, viewport)
+            } else {
+                (self.post_targets.view(index + 1), target_viewport)
+            };
+            let input = self.post_targets.input(index);
+            let mut render_pass = begin_render_pass(&mut encoder, target, border);
+            set_viewport(&mut render_pass, pass_viewport);
+            match (pass, &self.user_shaders) {
+                (PostPass::Upscale, _) => self.upscale.draw(&mut render_pass, input),
+                (PostPass::User(shader), Some(user_shaders)) => user_shaders.draw(&mut render_pass, *shader, input),
+                (PostPass::User(_), None) => {},
+                (PostPass::Crt, _) => self.crt.draw(&mut render_pass, input),
+            }
+            if let (true, Some(debug_ui)) = (last, &self.debug_ui) {
+                debug_ui.render(&mut render_pass, &screen_descriptor);
+            }
+        }
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+        if let Some(debug_ui) = &mut self.debug_ui {
+            debug_ui.finish_frame();
+        }
+    }
+}
+
+// Passes run after the display is drawn
+enum PostPass {
+    Upscale,
+    // The index of a user shader that compiled
+    User(usize),
+    Crt,
+}
+
+fn begin_render_pass<'a>(
+    encoder: &'a mut wgpu::CommandEncoder,
+    view: &'a wgpu::TextureView,
+    clear: wgpu::Color,
+) -> wgpu::RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Render Pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(clear),
+                store: true,
+            },
+        })],
+        depth_stencil_attachment: None,
+    })
+}
+
+fn set_viewport(render_pass: &mut wgpu::RenderPass, viewport: Viewport) {
+    render_pass.set_viewport(
+        viewport.x as f32,
+        viewport.y as f32,
+        viewport.width as f32,
+        viewport.height as f32,
+        0.0,
+        1.0,
+    );
+}
+
+// If there's no hardware adapter tries the backend's software one before giving up. Without a
+// surface any adapter will do.
+async fn request_adapter(
+    instance: &wgpu::Instance,
+    surface: Option<&wgpu::Surface>,
+) -> Result<wgpu::Adapter, StartupError> {
+    for force_fallback_adapter in [false, true] {
+        let adapter = instance.request_adapter(
+            &wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: surface,
+                force_fallback_adapter,
+            }
+        ).await;
+        if let Some(adapter) = adapter {
+            log::info!("Using {:?}", adapter.get_info());
+            return Ok(adapter);
+        }
+        log::warn!("No {} adapter found", if force_fallback_adapter { "fallback" } else { "hardware" });
+    }
+    Err(StartupError::NoAdapter)
+}
+
+async fn request_device(adapter: &wgpu::Adapter) -> Result<(wgpu::Device, wgpu::Queue), StartupError> {
+    adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            features: wgpu::Features::empty(),
+            limits: wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits()),
+            label: None,
+        },
+        None // Trace path
+    ).await.map_err(StartupError::Device)
+}
+
+// sRGB like the window's surface, so frames read back the way they'd be shown
+fn create_offscreen_texture(device: &wgpu::Device, width: u32, height: u32) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        label: Some("Offscreen texture"),
+        view_formats: &[],
+    })
+}
+
+// Auto follows the WGPU_BACKEND environment variable and otherwise tries them all
+fn backends(backend: GpuBackend) -> wgpu::Backends {
+    match backend {
+        GpuBackend::Auto => wgpu::util::backend_bits_from_env().unwrap_or(wgpu::Backends::all()),
+        GpuBackend::Vulkan => wgpu::Backends::VULKAN,
+        GpuBackend::Metal => wgpu::Backends::METAL,
+        GpuBackend::Dx12 => wgpu::Backends::DX12,
+        GpuBackend::Dx11 => wgpu::Backends::DX11,
+        GpuBackend::Gl => wgpu::Backends::GL,
+    }
+}
+
+// Fifo is essentially vsync and always supported. Without vsync the lowest latency mode available
+// wins.
+fn present_mode(vsync: bool, available: &[wgpu::PresentMode]) -> wgpu::PresentMode {
+    let preferred: &[wgpu::PresentMode] = if vsync {
+        &[wgpu::PresentMode::Fifo]
+    } else {
+        &[wgpu::PresentMode::Immediate, wgpu::PresentMode::Mailbox, wgpu::PresentMode::FifoRelaxed]
+    };
+    preferred.iter()
+        .copied()
+        .find(|mode| available.contains(mode))
+        .unwrap_or(wgpu::PresentMode::Fifo)
+}
+
+// The display textures are recreated whenever the Chip8 changes resolution
+fn create_display_textures(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    uniform_buffer: &wgpu::Buffer,
+    width: usize,
+    height: usize,
+) -> (wgpu::Texture, wgpu::Texture, wgpu::BindGroup) {
+    let create_texture = |format, label| device.create_texture(
+        &wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: width as u32,
+                height: height as u32,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            label: Some(label),
+            view_formats: &[],
+        }
+    );
+    let texture = create_texture(wgpu::TextureFormat::Rgba8UnormSrgb, "Chip8 display texture");
+    let planes_texture = create_texture(wgpu::TextureFormat::R8Uint, "Chip8 planes texture");
+
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let planes_view = planes_texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&planes_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: uniform_buffer.as_entire_binding(),
+            },
+        ],
+        label: Some("diffuse_bind_group"),
+    });
+
+    (texture, planes_texture, bind_group)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timing::INSTRUCTIONS_PER_FRAME;
+    use std::fs::File;
+    use std::path::PathBuf;
+
+    // The IBM logo after it's been drawn
+    fn ibm_logo(args: &[&str]) -> (Config, Chip8) {
+        let config = Config::from_args(args.iter().map(|arg| arg.to_string())).unwrap();
+        let mut chip8 = config.create_chip8(&config.load_rom().unwrap()).unwrap();
+        for _ in 0..10 {
+            chip8.run_frame(INSTRUCTIONS_PER_FRAME);
+        }
+        (config, chip8)
+    }
+
+    // Renders a frame offscreen and compares it with src/renderer/golden/`name`.png. Without an
+    // adapter to render with the test fails, unless WGPUCHIP8_SKIP_GOLDEN=1 skips it. Run with
+    // UPDATE_GOLDEN=1 to write the images.
+    fn assert_golden(name: &str, config: &Config, chip8: &Chip8, width: u32, height: u32) {
+        let mut renderer = match pollster::block_on(WgpuRenderer::offscreen(config, chip8, width, height)) {
+            Ok(renderer) => renderer,
+            Err(err) if std::env::var_os("WGPUCHIP8_SKIP_GOLDEN").is_some() => {
+                eprintln!("Skipping the {} golden image: {}", name, err);
+                return;
+            },
+            Err(err) => panic!(
+                "Unable to render the {} golden image: {} (set WGPUCHIP8_SKIP_GOLDEN=1 to skip it)",
+                name, err
+            ),
+        };
+        let pixels = renderer.render_offscreen(chip8, None).unwrap();
+        assert_eq!(pixels.len(), (width * height * 4) as usize);
+
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/renderer/golden").join(format!("{}.png", name));
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            let mut encoder = png::Encoder::new(File::create(&path).unwrap(), width, height);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.write_header().unwrap().write_image_data(&pixels).unwrap();
+            return;
+        }
+        let mut reader = png::Decoder::new(File::open(&path).unwrap()).read_info().unwrap();
+        let mut golden = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut golden).unwrap();
+        assert_eq!((info.width, info.height), (width, height));
+        // Adapters round and filter a little differently
+        let differences = pixels.iter().zip(&golden).filter(|(a, b)| a.abs_diff(**b) > 2).count();
+        assert!(differences <= pixels.len() / 1000, "{} channels differ from {}", differences, path.display());
+    }
+
+    #[test]
+    fn test_golden_display() {
+        let (config, chip8) = ibm_logo(&[]);
+        assert_golden("display", &config, &chip8, 128, 64);
+    }
+
+    #[test]
+    fn test_golden_palette() {
+        // Integer scaling leaves a border around the display
+        let (config, chip8) = ibm_logo(&["--theme", "amber", "--border", "203040", "--scale", "integer"]);
+        assert_golden("palette", &config, &chip8, 200, 100);
+    }
+
+    #[test]
+    fn test_golden_post_processing() {
+        let (config, chip8) = ibm_logo(&["--upscale", "xbr", "--crt"]);
+        assert_golden("post-processing", &config, &chip8, 256, 128);
+    }
+
+    #[test]
+    fn test_present_mode() {
+        use wgpu::PresentMode::*;
+        assert_eq!(present_mode(true, &[Immediate, Fifo]), Fifo);
+        assert_eq!(present_mode(false, &[Fifo, Mailbox]), Mailbox);
+        assert_eq!(present_mode(false, &[Fifo]), Fifo);
+    }
+
+    #[test]
+    fn test_display_uniform() {
+        let uniform = DisplayUniform::new(&[[0x00, 0x00, 0x00, 0xFF], [0xFF, 0x80, 0xFF, 0xFF]], false);
+        assert_eq!(uniform.palette[0], [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(uniform.palette[1][0], 1.0);
+        assert!((uniform.palette[1][1] - 0.2158).abs() < 0.001);
+        assert_eq!(std::mem::size_of::<DisplayUniform>(), 272);
+    }
+
+    #[test]
+    fn test_shader() {
+        let module = naga::front::wgsl::parse_str(include_str!("shader.wgsl")).unwrap();
+        naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::empty())
+            .validate(&module)
+            .unwrap();
+    }
+}
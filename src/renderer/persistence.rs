@@ -0,0 +1,167 @@
+use super::display_rgba;
+use crate::chip8::Chip8;
+use crate::config::Persistence;
+use crate::palette::Palette;
+use std::collections::VecDeque;
+
+// Hides the flicker of sprites being erased and redrawn by remembering earlier frames. It works
+// on RGBA so every screen and palette is handled the same, whether the frame goes to a window or
+// a file. Each call to `blend` advances the history by a frame, so the App blends once per
+// emulated frame and the renderers and captures show the frame it keeps.
+pub struct FrameBlender {
+    mode: Persistence,
+    size: (usize, usize),
+    // The last blended frame, empty until the first
+    output: Vec<u8>,
+    // Blend: what was last presented, kept as floats so it fades smoothly
+    history: Vec<f32>,
+    // Or: the most recent frames, newest first
+    frames: VecDeque<Vec<u8>>,
+    settled: bool,
+}
+
+impl FrameBlender {
+    pub fn new(mode: Persistence) -> Self {
+        Self {
+            mode,
+            size: (0, 0),
+            output: Vec::new(),
+            history: Vec::new(),
+            frames: VecDeque::new(),
+            settled: true,
+        }
+    }
+
+    // Blends the Chip8's screen in as the next frame
+    pub fn blend_display(&mut self, chip8: &Chip8, palette: &Palette) {
+        let (width, height, pixels) = display_rgba(chip8, palette);
+        self.blend(width, height, &pixels, palette.background());
+    }
+
+    // Returns the frame to present in place of `rgba`. Pixels that are `background` in a frame
+    // count as unlit when ORing frames.
+    pub fn blend(&mut self, width: usize, height: usize, rgba: &[u8], background: [u8; 4]) -> &[u8] {
+        // Nothing to blend with after a resolution change
+        if self.size != (width, height) {
+            self.size = (width, height);
+            self.history = rgba.iter().map(|&value| value as f32).collect();
+            self.frames.clear();
+        }
+        self.output = match self.mode {
+            Persistence::Off => {
+                self.settled = true;
+                rgba.to_vec()
+            },
+            Persistence::Blend { strength } => self.blend_history(rgba, strength),
+            Persistence::Or { frames } => self.or_frames(rgba, background, frames),
+        };
+        &self.output
+    }
+
+    // The width, height and RGBA pixels of the last blended frame
+    pub fn frame(&self) -> Option<(usize, usize, &[u8])> {
+        (!self.output.is_empty()).then_some((self.size.0, self.size.1, self.output.as_slice()))
+    }
+
+    // Forgets the earlier frames, for when they were drawn in other colors
+    pub fn reset(&mut self) {
+        self.size = (0, 0);
+        self.output.clear();
+        self.settled = true;
+    }
+
+    // True once blending more frames of an unchanged screen wouldn't change the result, until
+    // then the screen has to be presented every frame
+    pub fn settled(&self) -> bool {
+        self.settled
+    }
+
+    fn blend_history(&mut self, rgba: &[u8], strength: f32) -> Vec<u8> {
+        self.settled = true;
+        for (history, &value) in self.history.iter_mut().zip(rgba) {
+            let value = value as f32;
+            *history = value + (*history - value) * strength;
+            if (*history - value).abs() < 0.5 {
+                *history = value;
+            } else {
+                self.settled = false;
+            }
+        }
+        self.history.iter().map(|&value| value.round() as u8).collect()
+    }
+
+    fn or_frames(&mut self, rgba: &[u8], background: [u8; 4], frames: usize) -> Vec<u8> {
+        self.frames.push_front(rgba.to_vec());
+        self.frames.truncate(frames.max(1));
+        self.settled = self.frames.iter().all(|frame| frame == rgba);
+
+        let mut output = rgba.to_vec();
+        for (index, pixel) in output.chunks_exact_mut(4).enumerate() {
+            if pixel != background {
+                continue;
+            }
+            let offset = index * 4;
+            if let Some(lit) = self.frames.iter().map(|frame| &frame[offset..offset + 4]).find(|&p| p != background) {
+                pixel.copy_from_slice(lit);
+            }
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: [u8; 4] = [0, 0, 0, 0xFF];
+    const WHITE: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+
+    #[test]
+    fn test_blend() {
+        let mut blender = FrameBlender::new(Persistence::Blend { strength: 0.5 });
+        assert_eq!(blender.blend(1, 1, &WHITE, BLACK), WHITE);
+        assert!(blender.settled());
+        // An erased pixel fades out over a few frames
+        assert_eq!(blender.blend(1, 1, &BLACK, BLACK), [0x80, 0x80, 0x80, 0xFF]);
+        assert!(!blender.settled());
+        assert_eq!(blender.blend(1, 1, &BLACK, BLACK), [0x40, 0x40, 0x40, 0xFF]);
+        for _ in 0..8 {
+            blender.blend(1, 1, &BLACK, BLACK);
+        }
+        assert!(blender.settled());
+        assert_eq!(blender.blend(1, 1, &BLACK, BLACK), BLACK);
+    }
+
+    #[test]
+    fn test_or_frames() {
+        let mut blender = FrameBlender::new(Persistence::Or { frames: 2 });
+        let frame = [WHITE, BLACK].concat();
+        let flickered = [BLACK, WHITE].concat();
+        assert_eq!(blender.blend(2, 1, &frame, BLACK), frame);
+        // A pixel stays lit for a frame after it was erased
+        assert_eq!(blender.blend(2, 1, &flickered, BLACK), [WHITE, WHITE].concat());
+        assert!(!blender.settled());
+        assert_eq!(blender.blend(2, 1, &flickered, BLACK), flickered);
+        assert!(blender.settled());
+    }
+
+    #[test]
+    fn test_frame() {
+        let mut blender = FrameBlender::new(Persistence::Blend { strength: 0.5 });
+        assert_eq!(blender.frame(), None);
+        blender.blend(1, 1, &WHITE, BLACK);
+        blender.blend(1, 1, &BLACK, BLACK);
+        assert_eq!(blender.frame(), Some((1, 1, &[0x80, 0x80, 0x80, 0xFF][..])));
+        // Fading starts over after a reset
+        blender.reset();
+        assert_eq!(blender.frame(), None);
+        assert_eq!(blender.blend(1, 1, &BLACK, BLACK), BLACK);
+    }
+
+    #[test]
+    fn test_resolution_change() {
+        let mut blender = FrameBlender::new(Persistence::Blend { strength: 0.9 });
+        blender.blend(1, 1, &WHITE, BLACK);
+        assert_eq!(blender.blend(2, 1, &[BLACK, BLACK].concat(), BLACK), [BLACK, BLACK].concat());
+    }
+}
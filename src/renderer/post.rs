@@ -0,0 +1,422 @@
+use super::Viewport;
+use bytemuck::Zeroable;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use wgpu::util::DeviceExt;
+
+// Declarations every user shader gets, documented in post.wgsl
+const PRELUDE: &str = include_str!("post.wgsl");
+// How often the shader directory is checked for changes
+const RELOAD_INTERVAL: Duration = Duration::from_millis(500);
+
+// Two textures the size of the viewport that post-processing passes take turns reading from and
+// drawing to. The display is drawn to the first one.
+pub struct PostTargets {
+    format: wgpu::TextureFormat,
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    size: (u32, u32),
+    targets: Vec<Target>,
+}
+
+struct Target {
+    view: wgpu::TextureView,
+    // Binds the texture as a pass's input
+    bind_group: wgpu::BindGroup,
+}
+
+impl PostTargets {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("Post input bind group layout"),
+        });
+        // Effects like curvature sample between pixels
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        Self {
+            format,
+            layout,
+            sampler,
+            size: (0, 0),
+            targets: Vec::new(),
+        }
+    }
+
+    // Bind group layout of a pass's input, group 0 in every post-processing shader
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, viewport: Viewport) {
+        let size = (viewport.width.max(1), viewport.height.max(1));
+        if size != self.size {
+            self.size = size;
+            self.targets = (0..2).map(|_| self.create_target(device)).collect();
+        }
+    }
+
+    pub fn view(&self, index: usize) -> &wgpu::TextureView {
+        &self.targets[index % 2].view
+    }
+
+    pub fn input(&self, index: usize) -> &wgpu::BindGroup {
+        &self.targets[index % 2].bind_group
+    }
+
+    fn create_target(&self, device: &wgpu::Device) -> Target {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: self.size.0,
+                height: self.size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            label: Some("Post target texture"),
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+            label: Some("Post input bind group"),
+        });
+        Target { view, bind_group }
+    }
+}
+
+// Group 1 of a post-processing shader, its uniforms
+pub fn uniform_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+        label: Some("Post uniform bind group layout"),
+    })
+}
+
+pub fn uniform_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    buffer: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            },
+        ],
+        label: Some("Post uniform bind group"),
+    })
+}
+
+// A pass drawing a triangle over the viewport with `shader`'s vs_main and fs_main
+pub fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Post pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+// Matches the Post struct in post.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PostUniform {
+    resolution: [f32; 2],
+    source_size: [f32; 2],
+    time: f32,
+    frame: u32,
+}
+
+// The .wgsl files in a directory, run in order of their names after the display is drawn. They're
+// reloaded when they change, a shader that doesn't compile is reported and skipped.
+pub struct UserShaders {
+    dir: PathBuf,
+    // The last problem reading `dir`, so it's only reported once
+    dir_error: Option<String>,
+    format: wgpu::TextureFormat,
+    frame: u32,
+    last_check: Instant,
+    layout: wgpu::PipelineLayout,
+    shaders: Vec<UserShader>,
+    start: Instant,
+    uniform_bind_group: wgpu::BindGroup,
+    uniform_buffer: wgpu::Buffer,
+}
+
+struct UserShader {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    // None if it didn't compile
+    pipeline: Option<wgpu::RenderPipeline>,
+}
+
+impl UserShaders {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        input_layout: &wgpu::BindGroupLayout,
+        dir: PathBuf,
+    ) -> Self {
+        let uniform_layout = uniform_layout(device);
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Post uniform buffer"),
+            contents: bytemuck::bytes_of(&PostUniform::zeroed()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let uniform_bind_group = uniform_bind_group(device, &uniform_layout, &uniform_buffer);
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("User shader pipeline layout"),
+            bind_group_layouts: &[input_layout, &uniform_layout],
+            push_constant_ranges: &[],
+        });
+        let mut shaders = Self {
+            dir,
+            dir_error: None,
+            format,
+            frame: 0,
+            last_check: Instant::now(),
+            layout,
+            shaders: Vec::new(),
+            start: Instant::now(),
+            uniform_bind_group,
+            uniform_buffer,
+        };
+        shaders.reload(device);
+        shaders
+    }
+
+    // Picks up shaders that were added, changed or removed since the last check
+    pub fn reload_changed(&mut self, device: &wgpu::Device) {
+        if self.last_check.elapsed() >= RELOAD_INTERVAL {
+            self.reload(device);
+        }
+    }
+
+    // Updates the uniforms for the next frame, `resolution` is the viewport's
+    pub fn prepare(&mut self, queue: &wgpu::Queue, resolution: Viewport, source_size: (usize, usize)) {
+        let uniform = PostUniform {
+            resolution: [resolution.width as f32, resolution.height as f32],
+            source_size: [source_size.0 as f32, source_size.1 as f32],
+            time: self.start.elapsed().as_secs_f32(),
+            frame: self.frame,
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+        self.frame = self.frame.wrapping_add(1);
+    }
+
+    // The number of shaders that compiled
+    pub fn pass_count(&self) -> usize {
+        self.pipelines().count()
+    }
+
+    // Draws the `index`th shader that compiled, reading from `input`
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, index: usize, input: &'a wgpu::BindGroup) {
+        if let Some(pipeline) = self.pipelines().nth(index) {
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, input, &[]);
+            render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+
+    fn pipelines(&self) -> impl Iterator<Item = &wgpu::RenderPipeline> {
+        self.shaders.iter().filter_map(|shader| shader.pipeline.as_ref())
+    }
+
+    fn reload(&mut self, device: &wgpu::Device) {
+        self.last_check = Instant::now();
+        let files = match shader_files(&self.dir) {
+            Ok(files) => {
+                self.dir_error = None;
+                files
+            },
+            Err(err) => {
+                let err = err.to_string();
+                if self.dir_error.as_ref() != Some(&err) {
+                    log::error!("Unable to read shaders from {}: {}", self.dir.display(), err);
+                    self.dir_error = Some(err);
+                }
+                Vec::new()
+            },
+        };
+
+        let mut previous = std::mem::take(&mut self.shaders);
+        for (path, modified) in files {
+            let unchanged = previous.iter().position(|shader| shader.path == path && shader.modified == modified);
+            let shader = match unchanged {
+                Some(index) => previous.swap_remove(index),
+                None => {
+                    let pipeline = match self.compile(device, &path) {
+                        Ok(pipeline) => {
+                            log::info!("Loaded shader {}", path.display());
+                            Some(pipeline)
+                        },
+                        Err(err) => {
+                            log::error!("Unable to compile {}:\n{}", path.display(), err);
+                            None
+                        },
+                    };
+                    UserShader { path, modified, pipeline }
+                },
+            };
+            self.shaders.push(shader);
+        }
+    }
+
+    fn compile(&self, device: &wgpu::Device, path: &Path) -> Result<wgpu::RenderPipeline, String> {
+        let source = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let source = with_prelude(&source)?;
+        // wgpu panics on errors it isn't told to expect
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: path.to_str(),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let pipeline = create_pipeline(device, &self.layout, &shader, self.format);
+        match pollster::block_on(device.pop_error_scope()) {
+            Some(err) => Err(err.to_string()),
+            None => Ok(pipeline),
+        }
+    }
+}
+
+// Sorted by name, which is the order they run in
+fn shader_files(dir: &Path) -> std::io::Result<Vec<(PathBuf, Option<SystemTime>)>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "wgsl") {
+            let modified = std::fs::metadata(&path).and_then(|metadata| metadata.modified()).ok();
+            files.push((path, modified));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// Returns the complete shader, or the errors wgpu would have found in it. The prelude goes after
+// the user's code so line numbers in errors match their file.
+fn with_prelude(source: &str) -> Result<String, String> {
+    let source = format!("{}\n{}", source, PRELUDE);
+    let module = naga::front::wgsl::parse_str(&source).map_err(|err| err.emit_to_string(&source))?;
+    naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::empty())
+        .validate(&module)
+        .map_err(|err| err.emit_to_string(&source))?;
+    let has_fs_main = module.entry_points.iter()
+        .any(|entry_point| entry_point.name == "fs_main" && entry_point.stage == naga::ShaderStage::Fragment);
+    if !has_fs_main {
+        return Err("missing a @fragment fn fs_main".to_string());
+    }
+    Ok(source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INVERT: &str = "
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_input, s_input, in.tex_coords);
+    return vec4<f32>(1.0 - color.rgb, f32(post.frame % 2u));
+}
+";
+
+    #[test]
+    fn test_with_prelude() {
+        assert!(with_prelude(INVERT).unwrap().starts_with(INVERT));
+        assert!(with_prelude("fn helper() {}").unwrap_err().contains("fs_main"));
+        let err = with_prelude("\n@fragment\nfn fs_main() -> @location(0) vec4<f32> { return undefined; }").unwrap_err();
+        assert!(err.contains("undefined"), "{}", err);
+    }
+
+    #[test]
+    fn test_examples() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("shaders");
+        let files = shader_files(&dir).unwrap();
+        assert!(!files.is_empty());
+        for (path, _) in files {
+            if let Err(err) = with_prelude(&std::fs::read_to_string(&path).unwrap()) {
+                panic!("{}: {}", path.display(), err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_post_uniform() {
+        // WGSL rounds the struct up to its 8 byte alignment
+        assert_eq!(std::mem::size_of::<PostUniform>(), 24);
+    }
+}
@@ -0,0 +1,44 @@
+// Appended to every user post-processing shader. A shader only has to define the fragment entry
+// point, for example:
+//
+//     @fragment
+//     fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+//         let color = textureSample(t_input, s_input, in.tex_coords);
+//         return vec4<f32>(1.0 - color.rgb, 1.0);
+//     }
+
+struct VertexOutput {
+	@builtin(position) clip_position: vec4<f32>,
+	// 0,0 is the top left of the input and 1,1 the bottom right
+	@location(0) tex_coords: vec2<f32>,
+};
+
+// Matches PostUniform in post.rs
+struct Post {
+	// Size of the output in pixels
+	resolution: vec2<f32>,
+	// Pixels in the emulated display
+	source_size: vec2<f32>,
+	// Seconds since the shaders were loaded
+	time: f32,
+	// Frames drawn since the shaders were loaded
+	frame: u32,
+};
+
+// The display, or the previous shader's output, in linear color at the output's size
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+@group(1) @binding(0)
+var<uniform> post: Post;
+
+// A triangle covering the viewport, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	var out: VertexOutput;
+	out.tex_coords = uv;
+	out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+	return out;
+}
@@ -0,0 +1,52 @@
+// Vertex shader
+
+struct VertexInput {
+	@location(0) position: vec3<f32>,
+	@location(1) tex_coords: vec2<f32>,
+};
+
+struct VertexOutput {
+	@builtin(position) clip_position: vec4<f32>,
+	@location(0) tex_coords: vec2<f32>,
+};
+
+@vertex
+fn vs_main(
+	model: VertexInput,
+) -> VertexOutput {
+	var out: VertexOutput;
+	out.tex_coords = model.tex_coords;
+	out.clip_position = vec4<f32>(model.position, 1.0);
+	return out;
+}
+
+// Fragment shader
+
+// Matches DisplayUniform in gpu.rs
+struct Display {
+	// Linear colors indexed by a pixel's planes
+	palette: array<vec4<f32>, 16>,
+	// Non-zero shows t_diffuse as it is instead of looking t_planes up in the palette
+	direct_color: u32,
+};
+
+@group(0) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(0) @binding(1)
+var s_diffuse: sampler;
+@group(0) @binding(2)
+var t_planes: texture_2d<u32>;
+@group(0) @binding(3)
+var<uniform> display: Display;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	let color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+	if display.direct_color != 0u {
+		return color;
+	}
+	let size = textureDimensions(t_planes);
+	let coords = min(vec2<u32>(in.tex_coords * vec2<f32>(size)), size - 1u);
+	let planes = textureLoad(t_planes, coords, 0).r;
+	return display.palette[planes & 15u];
+}
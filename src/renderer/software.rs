@@ -0,0 +1,136 @@
+use super::upscale::upscale;
+use super::{display_rgba, viewport, FrameBlender, RenderError, Renderer, StartupError, Viewport};
+use crate::chip8::Chip8;
+use crate::config::{Config, ScaleMode, Upscaler};
+use crate::debug_ui::DebugControls;
+use crate::palette::Palette;
+use softbuffer::{Context, Surface};
+use std::num::NonZeroU32;
+use std::ops::Range;
+use winit::{dpi::PhysicalSize, window::Window};
+
+// Scales the display on the CPU and copies it to the window through softbuffer, for machines
+// without a usable GPU. There's no debug overlay.
+pub struct SoftwareRenderer {
+    // Dropped before the context it was created from
+    surface: Surface,
+    _context: Context,
+    palette: Palette,
+    scale: ScaleMode,
+    size: PhysicalSize<u32>,
+    upscaler: Upscaler,
+}
+
+impl SoftwareRenderer {
+    pub fn new(config: &Config, window: &Window) -> Result<Self, StartupError> {
+        // Safety: The App owns the window and drops the renderer first
+        let context = unsafe { Context::new(window) }.map_err(StartupError::Software)?;
+        let surface = unsafe { Surface::new(&context, window) }.map_err(StartupError::Software)?;
+        let mut renderer = Self {
+            surface,
+            _context: context,
+            palette: config.palette,
+            scale: config.scale,
+            size: PhysicalSize::new(0, 0),
+            upscaler: config.upscaler,
+        };
+        renderer.resize(window.inner_size());
+        Ok(renderer)
+    }
+}
+
+impl Renderer for SoftwareRenderer {
+    fn resize(&mut self, size: PhysicalSize<u32>) {
+        if let (Some(width), Some(height)) = (NonZeroU32::new(size.width), NonZeroU32::new(size.height)) {
+            match self.surface.resize(width, height) {
+                Ok(()) => self.size = size,
+                Err(err) => log::warn!("Unable to resize the software rendering surface: {}", err),
+            }
+        }
+    }
+
+    fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    // The whole window is drawn every time, so the dirty rows don't matter
+    fn render(
+        &mut self,
+        _window: &Window,
+        chip8: &Chip8,
+        _dirty_rows: Option<Range<usize>>,
+        blender: Option<&FrameBlender>,
+        _controls: &mut DebugControls,
+    ) -> Result<(), RenderError> {
+        if self.size.width == 0 || self.size.height == 0 {
+            return Ok(());
+        }
+        let display;
+        let (width, height, pixels) = match blender.and_then(FrameBlender::frame) {
+            Some(frame) => frame,
+            None => {
+                display = display_rgba(chip8, &self.palette);
+                (display.0, display.1, display.2.as_slice())
+            },
+        };
+        // The viewport keeps the display's size, the upscaled image is just more detailed
+        let viewport = viewport(self.scale, (width, height), self.size);
+        let (width, height, pixels) = upscale(self.upscaler, width, height, pixels);
+        let mut buffer = self.surface.buffer_mut().map_err(|err| RenderError::Other(err.to_string()))?;
+        let border = to_0rgb(self.palette.border);
+        blit(&pixels, width, height, &mut buffer, self.size.width as usize, viewport, border);
+        buffer.present().map_err(|err| RenderError::Other(err.to_string()))
+    }
+}
+
+// softbuffer's pixel format
+fn to_0rgb(color: [u8; 4]) -> u32 {
+    u32::from_be_bytes([0, color[0], color[1], color[2]])
+}
+
+// Scales the RGBA display into `viewport` of `target` with nearest neighbour sampling and fills
+// the rest with `border`
+fn blit(
+    rgba: &[u8],
+    width: usize,
+    height: usize,
+    target: &mut [u32],
+    target_width: usize,
+    viewport: Viewport,
+    border: u32,
+) {
+    let (left, top) = (viewport.x as usize, viewport.y as usize);
+    let (right, bottom) = (left + viewport.width as usize, top + viewport.height as usize);
+    for (y, row) in target.chunks_mut(target_width).enumerate() {
+        for (x, pixel) in row.iter_mut().enumerate() {
+            *pixel = if (left..right).contains(&x) && (top..bottom).contains(&y) {
+                let source_x = (x - left) * width / viewport.width as usize;
+                let source_y = (y - top) * height / viewport.height as usize;
+                let offset = (source_y * width + source_x) * 4;
+                to_0rgb([rgba[offset], rgba[offset + 1], rgba[offset + 2], 0xFF])
+            } else {
+                border
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BORDER: u32 = 0x123456;
+
+    #[test]
+    fn test_blit() {
+        let rgba = [0xFF, 0x00, 0x00, 0xFF, 0x00, 0x00, 0xFF, 0xFF];
+        let mut target = vec![0; 10 * 10];
+        blit(&rgba, 2, 1, &mut target, 10, Viewport { x: 1, y: 3, width: 8, height: 4 }, BORDER);
+        assert_eq!(target[0], BORDER);
+        assert_eq!(target[3 * 10 + 1], 0xFF0000);
+        assert_eq!(target[6 * 10 + 4], 0xFF0000);
+        assert_eq!(target[3 * 10 + 5], 0x0000FF);
+        assert_eq!(target[3 * 10 + 9], BORDER);
+        assert_eq!(target[7 * 10 + 5], BORDER);
+    }
+}
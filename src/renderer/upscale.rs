@@ -0,0 +1,275 @@
+use super::post::{create_pipeline, uniform_bind_group, uniform_layout};
+use crate::config::Upscaler;
+use wgpu::util::DeviceExt;
+
+type Pixel = [u8; 4];
+
+// Runs `upscaler` over an RGBA image, returning the new width, height and pixels. upscale.wgsl
+// does the same on the GPU.
+pub fn upscale(upscaler: Upscaler, width: usize, height: usize, rgba: &[u8]) -> (usize, usize, Vec<u8>) {
+    let factor = upscaler.factor();
+    let pixels: Vec<Pixel> = rgba.chunks_exact(4).map(|pixel| [pixel[0], pixel[1], pixel[2], pixel[3]]).collect();
+    let (output_width, output_height) = (width * factor, height * factor);
+    let mut output = vec![0; output_width * output_height * 4];
+    for y in 0..height {
+        for x in 0..width {
+            // Neighbours past the edges repeat the edge
+            let at = |dx: isize, dy: isize| {
+                let x = (x as isize + dx).clamp(0, width as isize - 1) as usize;
+                let y = (y as isize + dy).clamp(0, height as isize - 1) as usize;
+                pixels[y * width + x]
+            };
+            let block = match upscaler {
+                Upscaler::None => vec![at(0, 0)],
+                Upscaler::Scale2x => scale2x(at).to_vec(),
+                Upscaler::Scale3x => scale3x(at).to_vec(),
+                Upscaler::Xbr => xbr(at).to_vec(),
+            };
+            for (index, pixel) in block.iter().enumerate() {
+                let (block_x, block_y) = (x * factor + index % factor, y * factor + index / factor);
+                let offset = (block_y * output_width + block_x) * 4;
+                output[offset..offset + 4].copy_from_slice(pixel);
+            }
+        }
+    }
+    (output_width, output_height, output)
+}
+
+// The neighbours are named
+//   A B C
+//   D E F
+//   G H I
+// and the blocks returned row by row
+fn scale2x(at: impl Fn(isize, isize) -> Pixel) -> [Pixel; 4] {
+    let (b, d, e, f, h) = (at(0, -1), at(-1, 0), at(0, 0), at(1, 0), at(0, 1));
+    if b == h || d == f {
+        return [e; 4];
+    }
+    [
+        if d == b { d } else { e },
+        if b == f { f } else { e },
+        if d == h { d } else { e },
+        if h == f { f } else { e },
+    ]
+}
+
+fn scale3x(at: impl Fn(isize, isize) -> Pixel) -> [Pixel; 9] {
+    let (a, b, c) = (at(-1, -1), at(0, -1), at(1, -1));
+    let (d, e, f) = (at(-1, 0), at(0, 0), at(1, 0));
+    let (g, h, i) = (at(-1, 1), at(0, 1), at(1, 1));
+    if b == h || d == f {
+        return [e; 9];
+    }
+    [
+        if d == b { d } else { e },
+        if (d == b && e != c) || (b == f && e != a) { b } else { e },
+        if b == f { f } else { e },
+        if (d == b && e != g) || (d == h && e != a) { d } else { e },
+        e,
+        if (b == f && e != i) || (h == f && e != c) { f } else { e },
+        if d == h { d } else { e },
+        if (d == h && e != i) || (h == f && e != g) { h } else { e },
+        if h == f { f } else { e },
+    ]
+}
+
+// Level 1 xBR at twice the size. Each corner of E is filled in from F or H when the pixels around
+// it line up along that corner's anti-diagonal more than its diagonal.
+fn xbr(at: impl Fn(isize, isize) -> Pixel) -> [Pixel; 4] {
+    // Rotating the neighbourhood a quarter turn clockwise at a time takes the bottom right corner
+    // to the bottom left, top left and top right
+    let corner = |turns: u32| {
+        let at = |dx: isize, dy: isize| {
+            let (dx, dy) = (0..turns).fold((dx, dy), |(dx, dy), _| (-dy, dx));
+            at(dx, dy)
+        };
+        let (e, f, h, i) = (at(0, 0), at(1, 0), at(0, 1), at(1, 1));
+        if e == f || e == h {
+            return e;
+        }
+        let anti_diagonal = color_distance(e, at(1, -1)) + color_distance(e, at(-1, 1))
+            + color_distance(i, at(2, 0)) + color_distance(i, at(0, 2))
+            + 4 * color_distance(h, f);
+        let diagonal = color_distance(h, at(-1, 0)) + color_distance(h, at(1, 2))
+            + color_distance(f, at(2, 1)) + color_distance(f, at(0, -1))
+            + 4 * color_distance(e, i);
+        if anti_diagonal < diagonal {
+            average(e, if color_distance(e, f) <= color_distance(e, h) { f } else { h })
+        } else {
+            e
+        }
+    };
+    [corner(2), corner(3), corner(1), corner(0)]
+}
+
+// Weighs differences in brightness over differences in color, like the eye does
+fn color_distance(a: Pixel, b: Pixel) -> u32 {
+    let [r, g, b] = [0, 1, 2].map(|channel| a[channel] as f32 - b[channel] as f32);
+    let y = 0.299 * r + 0.587 * g + 0.114 * b;
+    let u = -0.169 * r - 0.331 * g + 0.5 * b;
+    let v = 0.5 * r - 0.419 * g - 0.081 * b;
+    (48.0 * y.abs() + 7.0 * u.abs() + 6.0 * v.abs()) as u32
+}
+
+fn average(a: Pixel, b: Pixel) -> Pixel {
+    [0, 1, 2, 3].map(|channel| a[channel].midpoint(b[channel]))
+}
+
+// Matches the Upscale struct in upscale.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct UpscaleUniform {
+    source_size: [f32; 2],
+    upscaler: u32,
+    _padding: u32,
+}
+
+impl UpscaleUniform {
+    fn new(upscaler: Upscaler, source_size: (usize, usize)) -> Self {
+        let upscaler = match upscaler {
+            Upscaler::None => 0,
+            Upscaler::Scale2x => 1,
+            Upscaler::Scale3x => 2,
+            Upscaler::Xbr => 3,
+        };
+        Self { source_size: [source_size.0 as f32, source_size.1 as f32], upscaler, _padding: 0 }
+    }
+}
+
+// The upscaler as a post-processing pass. It runs first, reading the display's pixels back out
+// of the viewport sized input.
+pub struct UpscalePass {
+    pipeline: wgpu::RenderPipeline,
+    upscaler: Upscaler,
+    uniform_bind_group: wgpu::BindGroup,
+    uniform_buffer: wgpu::Buffer,
+}
+
+impl UpscalePass {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        input_layout: &wgpu::BindGroupLayout,
+        upscaler: Upscaler,
+    ) -> Self {
+        let uniform_layout = uniform_layout(device);
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Upscale uniform buffer"),
+            contents: bytemuck::bytes_of(&UpscaleUniform::new(upscaler, (1, 1))),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let uniform_bind_group = uniform_bind_group(device, &uniform_layout, &uniform_buffer);
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("upscale.wgsl"));
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Upscale pipeline layout"),
+            bind_group_layouts: &[input_layout, &uniform_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_pipeline(device, &layout, &shader, format);
+
+        Self {
+            pipeline,
+            upscaler,
+            uniform_bind_group,
+            uniform_buffer,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.upscaler != Upscaler::None
+    }
+
+    pub fn prepare(&self, queue: &wgpu::Queue, source_size: (usize, usize)) {
+        let uniform = UpscaleUniform::new(self.upscaler, source_size);
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    // Draws `input` to the render pass's viewport
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, input: &'a wgpu::BindGroup) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, input, &[]);
+        render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const O: Pixel = [0, 0, 0, 0xFF];
+    const X: Pixel = [0xFF, 0xFF, 0xFF, 0xFF];
+
+    fn image(pixels: &[Pixel]) -> Vec<u8> {
+        pixels.concat()
+    }
+
+    #[test]
+    fn test_none() {
+        let rgba = image(&[X, O]);
+        assert_eq!(upscale(Upscaler::None, 2, 1, &rgba), (2, 1, rgba));
+    }
+
+    #[test]
+    fn test_scale2x() {
+        // A diagonal step gets its inner corner filled
+        let rgba = image(&[
+            X, O,
+            X, X,
+        ]);
+        let (width, height, output) = upscale(Upscaler::Scale2x, 2, 2, &rgba);
+        assert_eq!((width, height), (4, 4));
+        assert_eq!(output, image(&[
+            X, X, O, O,
+            X, X, X, O,
+            X, X, X, X,
+            X, X, X, X,
+        ]));
+    }
+
+    #[test]
+    fn test_scale3x() {
+        let rgba = image(&[
+            X, O,
+            X, X,
+        ]);
+        let (width, height, output) = upscale(Upscaler::Scale3x, 2, 2, &rgba);
+        assert_eq!((width, height), (6, 6));
+        assert_eq!(output, image(&[
+            X, X, X, O, O, O,
+            X, X, X, X, O, O,
+            X, X, X, X, X, O,
+            X, X, X, X, X, X,
+            X, X, X, X, X, X,
+            X, X, X, X, X, X,
+        ]));
+    }
+
+    #[test]
+    fn test_xbr() {
+        // The gaps beside a diagonal line are half filled in, the line itself stays intact
+        let rgba = image(&[
+            X, O, O,
+            O, X, O,
+            O, O, X,
+        ]);
+        let (width, height, output) = upscale(Upscaler::Xbr, 3, 3, &rgba);
+        assert_eq!((width, height), (6, 6));
+        let at = |x: usize, y: usize| &output[(y * width + x) * 4..(y * width + x) * 4 + 4];
+        let half = average(X, O);
+        assert_eq!(at(0, 0), X);
+        assert_eq!(at(3, 3), X);
+        assert_eq!(at(2, 1), half);
+        assert_eq!(at(1, 2), half);
+        assert_eq!(at(5, 0), O);
+    }
+
+    #[test]
+    fn test_shader() {
+        let module = naga::front::wgsl::parse_str(include_str!("upscale.wgsl")).unwrap();
+        naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::empty())
+            .validate(&module)
+            .unwrap();
+    }
+}
@@ -0,0 +1,155 @@
+// Vertex shader
+
+struct VertexOutput {
+	@builtin(position) clip_position: vec4<f32>,
+	@location(0) tex_coords: vec2<f32>,
+};
+
+// A triangle covering the viewport, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	var out: VertexOutput;
+	out.tex_coords = uv;
+	out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+	return out;
+}
+
+// Fragment shader, the same upscalers as upscale.rs
+
+// Matches UpscaleUniform in upscale.rs
+struct Upscale {
+	// Pixels in the emulated display
+	source_size: vec2<f32>,
+	// 1 is Scale2x, 2 is Scale3x and 3 is xBR
+	upscaler: u32,
+};
+
+// The display at the viewport's size
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+@group(1) @binding(0)
+var<uniform> upscale: Upscale;
+
+// The display pixel at `cell`, repeating the edges. Loaded from the middle of the pixel so it
+// comes out exactly, neighbours are compared for equality.
+fn fetch(cell: vec2<i32>) -> vec4<f32> {
+	let clamped = clamp(cell, vec2<i32>(0), vec2<i32>(upscale.source_size) - 1);
+	let size = vec2<f32>(textureDimensions(t_input));
+	let coords = vec2<i32>((vec2<f32>(clamped) + 0.5) / upscale.source_size * size);
+	return textureLoad(t_input, coords, 0);
+}
+
+fn same(a: vec4<f32>, b: vec4<f32>) -> bool {
+	return all(a == b);
+}
+
+// The neighbours are named
+//   A B C
+//   D E F
+//   G H I
+// and `sub` is the part of the block being drawn
+fn scale2x(cell: vec2<i32>, sub: vec2<u32>) -> vec4<f32> {
+	let b = fetch(cell + vec2<i32>(0, -1));
+	let d = fetch(cell + vec2<i32>(-1, 0));
+	let e = fetch(cell);
+	let f = fetch(cell + vec2<i32>(1, 0));
+	let h = fetch(cell + vec2<i32>(0, 1));
+	if same(b, h) || same(d, f) {
+		return e;
+	}
+	// The neighbours either side of this corner
+	let across = select(d, f, sub.x == 1u);
+	let along = select(b, h, sub.y == 1u);
+	return select(e, across, same(across, along));
+}
+
+fn scale3x(cell: vec2<i32>, sub: vec2<u32>) -> vec4<f32> {
+	let a = fetch(cell + vec2<i32>(-1, -1));
+	let b = fetch(cell + vec2<i32>(0, -1));
+	let c = fetch(cell + vec2<i32>(1, -1));
+	let d = fetch(cell + vec2<i32>(-1, 0));
+	let e = fetch(cell);
+	let f = fetch(cell + vec2<i32>(1, 0));
+	let g = fetch(cell + vec2<i32>(-1, 1));
+	let h = fetch(cell + vec2<i32>(0, 1));
+	let i = fetch(cell + vec2<i32>(1, 1));
+	if same(b, h) || same(d, f) {
+		return e;
+	}
+	switch sub.y * 3u + sub.x {
+		case 0u: { return select(e, d, same(d, b)); }
+		case 1u: { return select(e, b, (same(d, b) && !same(e, c)) || (same(b, f) && !same(e, a))); }
+		case 2u: { return select(e, f, same(b, f)); }
+		case 3u: { return select(e, d, (same(d, b) && !same(e, g)) || (same(d, h) && !same(e, a))); }
+		case 5u: { return select(e, f, (same(b, f) && !same(e, i)) || (same(h, f) && !same(e, c))); }
+		case 6u: { return select(e, d, same(d, h)); }
+		case 7u: { return select(e, h, (same(d, h) && !same(e, i)) || (same(h, f) && !same(e, g))); }
+		case 8u: { return select(e, f, same(h, f)); }
+		default: { return e; }
+	}
+}
+
+// Weighs differences in brightness over differences in color
+fn color_distance(a: vec4<f32>, b: vec4<f32>) -> f32 {
+	let difference = (a.rgb - b.rgb) * 255.0;
+	let y = dot(difference, vec3<f32>(0.299, 0.587, 0.114));
+	let u = dot(difference, vec3<f32>(-0.169, -0.331, 0.5));
+	let v = dot(difference, vec3<f32>(0.5, -0.419, -0.081));
+	return 48.0 * abs(y) + 7.0 * abs(u) + 6.0 * abs(v);
+}
+
+// `turns` quarter turns clockwise
+fn rotate(offset: vec2<i32>, turns: u32) -> vec2<i32> {
+	var rotated = offset;
+	for (var turn = 0u; turn < turns; turn++) {
+		rotated = vec2<i32>(-rotated.y, rotated.x);
+	}
+	return rotated;
+}
+
+// The neighbour at `offset` from the bottom right corner's point of view
+fn around(cell: vec2<i32>, offset: vec2<i32>, turns: u32) -> vec4<f32> {
+	return fetch(cell + rotate(offset, turns));
+}
+
+// Level 1 xBR at twice the size, each corner is handled as the bottom right one rotated
+fn xbr(cell: vec2<i32>, sub: vec2<u32>) -> vec4<f32> {
+	let turns = select(select(2u, 3u, sub.x == 1u), select(1u, 0u, sub.x == 1u), sub.y == 1u);
+	let e = fetch(cell);
+	let f = around(cell, vec2<i32>(1, 0), turns);
+	let h = around(cell, vec2<i32>(0, 1), turns);
+	let i = around(cell, vec2<i32>(1, 1), turns);
+	if same(e, f) || same(e, h) {
+		return e;
+	}
+	let anti_diagonal = color_distance(e, around(cell, vec2<i32>(1, -1), turns))
+		+ color_distance(e, around(cell, vec2<i32>(-1, 1), turns))
+		+ color_distance(i, around(cell, vec2<i32>(2, 0), turns))
+		+ color_distance(i, around(cell, vec2<i32>(0, 2), turns))
+		+ 4.0 * color_distance(h, f);
+	let diagonal = color_distance(h, around(cell, vec2<i32>(-1, 0), turns))
+		+ color_distance(h, around(cell, vec2<i32>(1, 2), turns))
+		+ color_distance(f, around(cell, vec2<i32>(2, 1), turns))
+		+ color_distance(f, around(cell, vec2<i32>(0, -1), turns))
+		+ 4.0 * color_distance(e, i);
+	if anti_diagonal < diagonal {
+		return mix(e, select(h, f, color_distance(e, f) <= color_distance(e, h)), 0.5);
+	}
+	return e;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	let position = in.tex_coords * upscale.source_size;
+	let cell = vec2<i32>(floor(position));
+	let sub = fract(position);
+	switch upscale.upscaler {
+		case 1u: { return scale2x(cell, min(vec2<u32>(sub * 2.0), vec2<u32>(1u))); }
+		case 2u: { return scale3x(cell, min(vec2<u32>(sub * 3.0), vec2<u32>(2u))); }
+		case 3u: { return xbr(cell, min(vec2<u32>(sub * 2.0), vec2<u32>(1u))); }
+		default: { return fetch(cell); }
+	}
+}
@@ -0,0 +1,208 @@
+use crate::chip8::{Chip8, Framebuffer, Keyboard};
+use crate::config::{Config, TerminalGlyphs};
+use crate::timing::{FrameClock, INSTRUCTIONS_PER_FRAME};
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::Print,
+    terminal,
+};
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Terminals only report key presses, so a key counts as held until this long after its last
+// press (or auto-repeat) arrives
+const KEY_RELEASE_DELAY: Duration = Duration::from_millis(150);
+
+pub fn run_terminal(config: Config, mut chip8: Chip8) -> io::Result<()> {
+    let _guard = TerminalGuard::enter()?;
+    let quit = chip8.quit.clone();
+    let input_thread = spawn_input_thread([chip8.keyboard.clone(), chip8.second_keyboard.clone()], quit.clone());
+
+    let mut stdout = io::stdout();
+    let mut clock = FrameClock::new();
+    let mut last_frame = String::new();
+    while !quit.load(Ordering::Relaxed) && !chip8.exited() {
+        let frames = clock.frames_due(Instant::now());
+        for _ in 0..frames {
+            chip8.run_frame(INSTRUCTIONS_PER_FRAME);
+        }
+
+        // Only frames that were drawn to are rendered, and only ones that look different printed
+        if chip8.take_dirty_rows().is_some() {
+            let frame = render(&chip8.framebuffer, config.terminal_glyphs);
+            if frame != last_frame {
+                // The frame shrinks when switching back to low resolution
+                if frame.len() != last_frame.len() {
+                    queue!(stdout, terminal::Clear(terminal::ClearType::All))?;
+                }
+                queue!(stdout, cursor::MoveTo(0, 0), Print(&frame))?;
+                stdout.flush()?;
+                last_frame = frame;
+            }
+        }
+        thread::sleep(clock.next_frame().saturating_duration_since(Instant::now()));
+    }
+
+    quit.store(true, Ordering::Relaxed);
+    input_thread.join().expect("input thread panicked")
+}
+
+// Restores the terminal when dropped, even if the emulator errors out
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide, terminal::Clear(terminal::ClearType::All))?;
+        Ok(Self)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn spawn_input_thread(keyboards: [Keyboard; 2], quit: Arc<AtomicBool>) -> thread::JoinHandle<io::Result<()>> {
+    thread::spawn(move || {
+        let mut release_at: [Option<Instant>; 2] = [None, None];
+        while !quit.load(Ordering::Relaxed) {
+            let timeout = release_at.iter().flatten().min()
+                .map_or(KEY_RELEASE_DELAY, |at| at.saturating_duration_since(Instant::now()));
+            if event::poll(timeout)? {
+                if let Event::Key(key) = event::read()? {
+                    if is_quit(&key) {
+                        quit.store(true, Ordering::Relaxed);
+                        // Wakes up an FX0A waiting on a key without pressing one, so the program
+                        // exits instead of seeing a key
+                        let (lock, cvar) = &*keyboards[0];
+                        let _keyboard = lock.lock().unwrap();
+                        cvar.notify_all();
+                        break;
+                    } else if let Some((keypad, key_pressed)) = keypad_key(&key) {
+                        let (lock, cvar) = &*keyboards[keypad];
+                        *lock.lock().unwrap() = Some(key_pressed);
+                        cvar.notify_all();
+                        release_at[keypad] = Some(Instant::now() + KEY_RELEASE_DELAY);
+                    }
+                }
+            }
+            for (keyboard, release_at) in keyboards.iter().zip(release_at.iter_mut()) {
+                if release_at.is_some_and(|at| at <= Instant::now()) {
+                    *keyboard.0.lock().unwrap() = None;
+                    *release_at = None;
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+fn is_quit(key: &KeyEvent) -> bool {
+    key.code == KeyCode::Esc || (key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL))
+}
+
+// Maps the left hand side of a QWERTY keyboard onto the hex keypad, and the right hand side
+// onto the CHIP-8X second keypad. Returns the keypad index and the key.
+//  1 2 3 4    7 8 9 0      1 2 3 C
+//  q w e r    u i o p  ->  4 5 6 D
+//  a s d f    j k l ;      7 8 9 E
+//  z x c v    m , . /      A 0 B F
+fn keypad_key(key: &KeyEvent) -> Option<(usize, u8)> {
+    const LAYOUT: [u8; 16] = [0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF];
+    const KEYPADS: [&str; 2] = ["1234qwerasdfzxcv", "7890uiopjkl;m,./"];
+    if key.kind == KeyEventKind::Release {
+        return None;
+    }
+    match key.code {
+        KeyCode::Char(c) => KEYPADS.iter().enumerate().find_map(|(keypad, keys)| {
+            keys.find(c.to_ascii_lowercase()).map(|position| (keypad, LAYOUT[position]))
+        }),
+        _ => None,
+    }
+}
+
+fn render(framebuffer: &Framebuffer, glyphs: TerminalGlyphs) -> String {
+    match glyphs {
+        TerminalGlyphs::HalfBlocks => render_half_blocks(framebuffer),
+        TerminalGlyphs::Braille => render_braille(framebuffer),
+    }
+}
+
+// Each character cell covers two vertically stacked pixels
+fn render_half_blocks(framebuffer: &Framebuffer) -> String {
+    let mut frame = String::new();
+    for y in (0..framebuffer.height()).step_by(2) {
+        for x in 0..framebuffer.width() {
+            frame.push(match (framebuffer.pixel(x, y), framebuffer.pixel(x, y + 1)) {
+                (false, false) => ' ',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (true, true) => '█',
+            });
+        }
+        frame.push_str("\r\n");
+    }
+    frame
+}
+
+// Each character cell covers a 2x4 block of pixels
+fn render_braille(framebuffer: &Framebuffer) -> String {
+    const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+    let mut frame = String::new();
+    for y in (0..framebuffer.height()).step_by(4) {
+        for x in (0..framebuffer.width()).step_by(2) {
+            let mut pattern = 0;
+            for (dy, row) in DOTS.iter().enumerate() {
+                for (dx, dot) in row.iter().enumerate() {
+                    if framebuffer.pixel(x + dx, y + dy) {
+                        pattern |= dot;
+                    }
+                }
+            }
+            frame.push(char::from_u32(0x2800 + pattern).unwrap());
+        }
+        frame.push_str("\r\n");
+    }
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_half_blocks() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.draw_sprite(0, 0, &[0b1000_0000, 0b1100_0000], 1);
+        let frame = render_half_blocks(&framebuffer);
+        assert!(frame.starts_with("█▄ "));
+        assert_eq!(frame.lines().count(), framebuffer.height() / 2);
+    }
+
+    #[test]
+    fn test_render_braille() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.draw_sprite(0, 0, &[0b1100_0000], 1);
+        let frame = render_braille(&framebuffer);
+        assert!(frame.starts_with('\u{2809}'));
+        assert_eq!(frame.lines().count(), framebuffer.height() / 4);
+    }
+
+    #[test]
+    fn test_keypad_key() {
+        let key = KeyEvent::new(KeyCode::Char('v'), KeyModifiers::NONE);
+        assert_eq!(keypad_key(&key), Some((0, 0xF)));
+        let key = KeyEvent::new(KeyCode::Char('p'), KeyModifiers::NONE);
+        assert_eq!(keypad_key(&key), Some((1, 0xD)));
+        let key = KeyEvent::new(KeyCode::Char('g'), KeyModifiers::NONE);
+        assert_eq!(keypad_key(&key), None);
+    }
+}
@@ -0,0 +1,58 @@
+use std::time::{Duration, Instant};
+
+// Timers count down at 60 Hz and the display is presented at the same rate
+pub const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+pub const INSTRUCTIONS_PER_FRAME: u32 = 10;
+// After a stall (window drag, pause, slow terminal) don't try to catch up on more than this
+const MAX_FRAMES_BEHIND: u32 = 4;
+
+#[derive(Debug)]
+pub struct FrameClock {
+    next_frame: Instant,
+}
+
+impl FrameClock {
+    pub fn new() -> Self {
+        Self { next_frame: Instant::now() }
+    }
+
+    // Returns how many emulated frames are due at `now`
+    pub fn frames_due(&mut self, now: Instant) -> u32 {
+        let mut frames = 0;
+        while self.next_frame <= now {
+            self.next_frame += FRAME_DURATION;
+            frames += 1;
+            if frames == MAX_FRAMES_BEHIND {
+                self.next_frame = now + FRAME_DURATION;
+                break;
+            }
+        }
+        frames
+    }
+
+    pub fn next_frame(&self) -> Instant {
+        self.next_frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_due() {
+        let mut clock = FrameClock::new();
+        let start = clock.next_frame();
+        assert_eq!(clock.frames_due(start), 1);
+        assert_eq!(clock.frames_due(start), 0);
+        assert_eq!(clock.frames_due(start + FRAME_DURATION * 2), 2);
+    }
+
+    #[test]
+    fn test_frames_due_caps_catch_up() {
+        let mut clock = FrameClock::new();
+        let late = clock.next_frame() + FRAME_DURATION * 100;
+        assert_eq!(clock.frames_due(late), MAX_FRAMES_BEHIND);
+        assert_eq!(clock.next_frame(), late + FRAME_DURATION);
+    }
+}
@@ -0,0 +1,223 @@
+mod cdp1802;
+mod cdp1861;
+
+use crate::chip8::Framebuffer;
+use cdp1802::{Bus, Cdp1802, INTERRUPT_CYCLES};
+use cdp1861::{Cdp1861, BYTES_PER_LINE, DISPLAY_LINES, DISPLAY_WIDTH};
+
+pub const RAM_SIZE: usize = 4096;
+pub const MONITOR_SIZE: usize = 512;
+const MONITOR_START: u16 = 0x8000;
+const PROGRAM_START: usize = 0x200;
+// Frontends get every other 1861 line, the CHIP-8 interpreter repeats each row at least twice
+const LINES_PER_ROW: usize = 2;
+
+// Everything on the VIP's bus apart from the CPU
+#[derive(Debug, Clone)]
+struct VipBus {
+    ram: Vec<u8>,
+    monitor: Vec<u8>,
+    // After a reset the monitor ROM also appears at 0000 until the first address with A15 set
+    monitor_shadow: bool,
+    video: Cdp1861,
+    key: Option<u8>,
+    key_latch: u8,
+}
+
+impl Bus for VipBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        if addr >= MONITOR_START {
+            self.monitor_shadow = false;
+        }
+        if addr >= MONITOR_START || self.monitor_shadow {
+            self.monitor[addr as usize % MONITOR_SIZE]
+        } else {
+            self.ram[addr as usize % RAM_SIZE]
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        if addr < MONITOR_START {
+            self.ram[addr as usize % RAM_SIZE] = value;
+        }
+    }
+
+    fn input(&mut self, port: u8) -> u8 {
+        if port == 1 {
+            self.video.set_enabled(true);
+        }
+        0
+    }
+
+    // OUT 1 turns the display off and OUT 2 selects the key EF3 reports on
+    fn output(&mut self, port: u8, value: u8) {
+        match port {
+            1 => self.video.set_enabled(false),
+            2 => self.key_latch = value & 0xF,
+            _ => {},
+        }
+    }
+
+    fn ef(&self, flag: u8) -> bool {
+        match flag {
+            1 => self.video.ef1(),
+            3 => self.key == Some(self.key_latch),
+            _ => false,
+        }
+    }
+}
+
+// A low level COSMAC VIP running the monitor ROM and CHIP-8 interpreter images supplied by the
+// user, so programs calling 1802 machine code through 0NNN run with the original timing.
+#[derive(Debug, Clone)]
+pub struct Vip {
+    cpu: Cdp1802,
+    bus: VipBus,
+}
+
+impl Vip {
+    // The interpreter is loaded at 0000. On reset the monitor sizes memory and then jumps there,
+    // unless C is held down.
+    pub fn new(monitor: &[u8], interpreter: &[u8]) -> Self {
+        let mut ram = vec![0; RAM_SIZE];
+        let len = interpreter.len().min(PROGRAM_START);
+        ram[..len].copy_from_slice(&interpreter[..len]);
+        let mut monitor = monitor.to_vec();
+        monitor.resize(MONITOR_SIZE, 0);
+        Self {
+            cpu: Cdp1802::new(),
+            bus: VipBus {
+                ram,
+                monitor,
+                monitor_shadow: true,
+                video: Cdp1861::new(),
+                key: None,
+                key_latch: 0,
+            },
+        }
+    }
+
+    pub fn load_program(&mut self, program: &[u8]) {
+        let len = program.len().min(RAM_SIZE - PROGRAM_START);
+        self.bus.ram[PROGRAM_START..PROGRAM_START + len].copy_from_slice(&program[..len]);
+    }
+
+    pub fn set_key(&mut self, key: Option<u8>) {
+        self.bus.key = key;
+    }
+
+    // The beeper is driven by Q
+    pub fn sound_on(&self) -> bool {
+        self.cpu.q
+    }
+
+    // The address of the next 1802 instruction, in R(P)
+    pub fn pc(&self) -> u16 {
+        self.cpu.r[self.cpu.p as usize]
+    }
+
+    // Runs one instruction, or gives the 1861 its DMA or interrupt if one is due
+    pub fn step(&mut self) {
+        if let Some(line) = self.bus.video.dma_due() {
+            let mut bytes = [0; BYTES_PER_LINE];
+            for byte in &mut bytes {
+                *byte = self.cpu.dma_out(&mut self.bus);
+            }
+            self.bus.video.store_line(line, bytes);
+            self.bus.video.advance(BYTES_PER_LINE as u32);
+        } else if self.bus.video.interrupt_due() && self.cpu.interrupt() {
+            self.bus.video.acknowledge_interrupt();
+            self.bus.video.advance(INTERRUPT_CYCLES);
+        } else {
+            let cycles = self.cpu.step(&mut self.bus);
+            self.bus.video.advance(cycles);
+        }
+    }
+
+    // Runs until the 1861 has shown a whole frame
+    pub fn run_frame(&mut self) {
+        let frames = self.bus.video.frames();
+        while self.bus.video.frames() == frames {
+            self.step();
+        }
+    }
+
+    pub fn render(&self, framebuffer: &mut Framebuffer) {
+        let height = DISPLAY_LINES / LINES_PER_ROW;
+        if (framebuffer.width(), framebuffer.height()) != (DISPLAY_WIDTH, height) {
+            framebuffer.set_resolution(DISPLAY_WIDTH, height);
+        }
+        for y in 0..height {
+            for x in 0..DISPLAY_WIDTH {
+                framebuffer.set_pixel(x, y, self.bus.video.pixel(x, y * LINES_PER_ROW));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Enables the display with a display interrupt routine that points R0 at 0100 every frame
+    const TEST_MONITOR: &[u8] = &[
+        0xC0, 0x80, 0x03,       // 8000: LBR 8003, leaving the shadow at 0000
+        0xF8, 0x80, 0xB3,       // 8003: R3 = 800A
+        0xF8, 0x0A, 0xA3,
+        0xD3,                   // 8009: SEP 3
+        0xF8, 0x80, 0xB1,       // 800A: R1 = 8020
+        0xF8, 0x20, 0xA1,
+        0xF8, 0x00, 0xB2,       // 8010: R2 = 00FF
+        0xF8, 0xFF, 0xA2,
+        0xE2,                   // 8016: SEX 2
+        0x62,                   // 8017: OUT 2
+        0x22,                   // 8018: DEC 2
+        0x69,                   // 8019: INP 1
+        0x30, 0x1A,             // 801A: BR 801A
+        0x00, 0x00, 0x00,
+        0x70,                   // 801F: RET
+        0x22, 0x78,             // 8020: DEC 2; SAV
+        0xF8, 0x01, 0xB0,       // R0 = 0100
+        0xF8, 0x00, 0xA0,
+        0x30, 0x1F,             // BR 801F
+    ];
+
+    #[test]
+    fn test_monitor_shadow() {
+        let mut vip = Vip::new(&[0xAA], &[0x55]);
+        assert_eq!(vip.bus.read(0x0000), 0xAA);
+        assert_eq!(vip.bus.read(0x8000), 0xAA);
+        assert_eq!(vip.bus.read(0x0000), 0x55);
+        vip.bus.write(0x8000, 0x11);
+        assert_eq!(vip.bus.read(0x8000), 0xAA);
+    }
+
+    #[test]
+    fn test_display_dma() {
+        let mut vip = Vip::new(TEST_MONITOR, &[]);
+        vip.bus.ram[0x100] = 0x80;
+        vip.bus.ram[0x108..0x110].fill(0xFF);
+        vip.bus.ram[0x117] = 0x01;
+        vip.run_frame();
+        vip.run_frame();
+
+        let mut framebuffer = Framebuffer::new();
+        vip.render(&mut framebuffer);
+        assert_eq!((framebuffer.width(), framebuffer.height()), (64, 64));
+        assert!(framebuffer.pixel(0, 0) && !framebuffer.pixel(1, 0));
+        assert!(framebuffer.pixel(63, 1) && !framebuffer.pixel(0, 1));
+        assert_eq!(vip.cpu.r[0], 0x100 + (DISPLAY_LINES * BYTES_PER_LINE) as u16);
+    }
+
+    #[test]
+    fn test_keypad() {
+        let mut vip = Vip::new(TEST_MONITOR, &[]);
+        vip.bus.ram[0xFF] = 0x0A;
+        vip.set_key(Some(0xA));
+        vip.run_frame();
+        assert_eq!(vip.bus.key_latch, 0xA);
+        assert!(vip.bus.ef(3));
+        vip.set_key(Some(0xB));
+        assert!(!vip.bus.ef(3));
+    }
+}
@@ -0,0 +1,423 @@
+// Everything the CPU is wired to: memory, the N lines used by INP/OUT and the EF flag inputs
+pub trait Bus {
+    fn read(&mut self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, value: u8);
+    fn input(&mut self, port: u8) -> u8;
+    fn output(&mut self, port: u8, value: u8);
+    // `flag` is 1 to 4, true when EFn is asserted
+    fn ef(&self, flag: u8) -> bool;
+}
+
+// Machine cycles, each of which is 8 clock pulses
+const SHORT_INSTRUCTION_CYCLES: u32 = 2;
+const LONG_INSTRUCTION_CYCLES: u32 = 3;
+pub const INTERRUPT_CYCLES: u32 = 1;
+
+// The RCA CDP1802 COSMAC
+#[derive(Debug, Clone, Default)]
+pub struct Cdp1802 {
+    pub r: [u16; 16],
+    pub p: u8,
+    pub x: u8,
+    pub d: u8,
+    pub df: bool,
+    pub t: u8,
+    pub ie: bool,
+    pub q: bool,
+    idle: bool,
+}
+
+impl Cdp1802 {
+    // The state after a reset, execution starts at R0
+    pub fn new() -> Self {
+        Self {
+            ie: true,
+            ..Self::default()
+        }
+    }
+
+    fn fetch(&mut self, bus: &mut impl Bus) -> u8 {
+        let byte = bus.read(self.r[self.p as usize]);
+        self.r[self.p as usize] = self.r[self.p as usize].wrapping_add(1);
+        byte
+    }
+
+    fn rx(&self) -> u16 {
+        self.r[self.x as usize]
+    }
+
+    fn inc(&mut self, n: u8) {
+        self.r[n as usize] = self.r[n as usize].wrapping_add(1);
+    }
+
+    fn dec(&mut self, n: u8) {
+        self.r[n as usize] = self.r[n as usize].wrapping_sub(1);
+    }
+
+    fn set_low(&mut self, n: u8, value: u8) {
+        self.r[n as usize] = (self.r[n as usize] & 0xFF00) | value as u16;
+    }
+
+    fn set_high(&mut self, n: u8, value: u8) {
+        self.r[n as usize] = (self.r[n as usize] & 0x00FF) | ((value as u16) << 8);
+    }
+
+    fn add(&mut self, a: u8, b: u8, carry: bool) {
+        let sum = a as u16 + b as u16 + carry as u16;
+        self.d = sum as u8;
+        self.df = sum > 0xFF;
+    }
+
+    // DF is set when there is no borrow
+    fn subtract(&mut self, a: u8, b: u8, borrow: bool) {
+        self.add(a, !b, !borrow);
+    }
+
+    // Returns false, and does nothing, if interrupts are disabled
+    pub fn interrupt(&mut self) -> bool {
+        if !self.ie {
+            return false;
+        }
+        self.t = (self.x << 4) | self.p;
+        self.p = 1;
+        self.x = 2;
+        self.ie = false;
+        self.idle = false;
+        true
+    }
+
+    // One DMA-out cycle, reads the byte at R0 for a peripheral
+    pub fn dma_out(&mut self, bus: &mut impl Bus) -> u8 {
+        let byte = bus.read(self.r[0]);
+        self.r[0] = self.r[0].wrapping_add(1);
+        self.idle = false;
+        byte
+    }
+
+    // Executes one instruction, returning the number of machine cycles it took. An idle CPU
+    // spends its cycles waiting for an interrupt or DMA.
+    pub fn step(&mut self, bus: &mut impl Bus) -> u32 {
+        if self.idle {
+            return SHORT_INSTRUCTION_CYCLES;
+        }
+        let opcode = self.fetch(bus);
+        let (i, n) = (opcode >> 4, opcode & 0xF);
+        match i {
+            0x0 if n == 0 => self.idle = true,
+            0x0 => self.d = bus.read(self.r[n as usize]),
+            0x1 => self.inc(n),
+            0x2 => self.dec(n),
+            0x3 => {
+                let target = self.fetch(bus);
+                if self.short_branch_taken(n, bus) {
+                    self.set_low(self.p, target);
+                }
+            },
+            0x4 => {
+                self.d = bus.read(self.r[n as usize]);
+                self.inc(n);
+            },
+            0x5 => bus.write(self.r[n as usize], self.d),
+            0x6 if n == 0 => self.inc(self.x),
+            0x6 if n < 8 => {
+                let value = bus.read(self.rx());
+                bus.output(n, value);
+                self.inc(self.x);
+            },
+            // 68 is only defined on the 1804/1805
+            0x6 if n == 8 => {},
+            0x6 => {
+                self.d = bus.input(n & 0x7);
+                bus.write(self.rx(), self.d);
+            },
+            0x7 => self.step_control(n, bus),
+            0x8 => self.d = self.r[n as usize] as u8,
+            0x9 => self.d = (self.r[n as usize] >> 8) as u8,
+            0xA => self.set_low(n, self.d),
+            0xB => self.set_high(n, self.d),
+            0xC => {
+                self.long_branch(n, bus);
+                return LONG_INSTRUCTION_CYCLES;
+            },
+            0xD => self.p = n,
+            0xE => self.x = n,
+            _ => self.step_arithmetic(n, bus),
+        }
+        SHORT_INSTRUCTION_CYCLES
+    }
+
+    fn condition(&self, n: u8, bus: &impl Bus) -> bool {
+        match n & 0x7 {
+            0 => true,
+            1 => self.q,
+            2 => self.d == 0,
+            3 => self.df,
+            flag => bus.ef(flag - 3),
+        }
+    }
+
+    // 30-37 branch if the condition holds, 38-3F if it does not
+    fn short_branch_taken(&self, n: u8, bus: &impl Bus) -> bool {
+        self.condition(n, bus) != (n >= 8)
+    }
+
+    // C0-CF mix long branches and long skips, only C4 (NOP) and C8 (LSKP) are unconditional
+    fn long_branch(&mut self, n: u8, bus: &mut impl Bus) {
+        let pc = self.r[self.p as usize];
+        let (skip, taken) = match n {
+            0x0..=0x3 => (false, self.condition(n, bus)),
+            0x4 => (true, false),
+            0x5 => (true, !self.q),
+            0x6 => (true, self.d != 0),
+            0x7 => (true, !self.df),
+            0x8 => (true, true),
+            0x9..=0xB => (false, !self.condition(n, bus)),
+            0xC => (true, self.ie),
+            0xD => (true, self.q),
+            0xE => (true, self.d == 0),
+            _ => (true, self.df),
+        };
+        if taken && !skip {
+            let target = u16::from_be_bytes([bus.read(pc), bus.read(pc.wrapping_add(1))]);
+            self.r[self.p as usize] = target;
+        } else if taken || !skip {
+            self.r[self.p as usize] = pc.wrapping_add(2);
+        }
+    }
+
+    fn step_control(&mut self, n: u8, bus: &mut impl Bus) {
+        match n {
+            0x0 | 0x1 => {
+                let t = bus.read(self.rx());
+                self.inc(self.x);
+                self.x = t >> 4;
+                self.p = t & 0xF;
+                self.ie = n == 0x0;
+            },
+            0x2 => {
+                self.d = bus.read(self.rx());
+                self.inc(self.x);
+            },
+            0x3 => {
+                bus.write(self.rx(), self.d);
+                self.dec(self.x);
+            },
+            0x4 => self.add(bus.read(self.rx()), self.d, self.df),
+            0x5 => self.subtract(bus.read(self.rx()), self.d, !self.df),
+            0x6 => {
+                let carry = self.df;
+                self.df = self.d & 1 == 1;
+                self.d = (self.d >> 1) | ((carry as u8) << 7);
+            },
+            0x7 => self.subtract(self.d, bus.read(self.rx()), !self.df),
+            0x8 => bus.write(self.rx(), self.t),
+            0x9 => {
+                self.t = (self.x << 4) | self.p;
+                bus.write(self.r[2], self.t);
+                self.x = self.p;
+                self.dec(2);
+            },
+            0xA => self.q = false,
+            0xB => self.q = true,
+            0xC => {
+                let value = self.fetch(bus);
+                self.add(value, self.d, self.df);
+            },
+            0xD => {
+                let value = self.fetch(bus);
+                self.subtract(value, self.d, !self.df);
+            },
+            0xE => {
+                let carry = self.df;
+                self.df = self.d & 0x80 != 0;
+                self.d = (self.d << 1) | carry as u8;
+            },
+            _ => {
+                let value = self.fetch(bus);
+                self.subtract(self.d, value, !self.df);
+            },
+        }
+    }
+
+    // F0-F7 operate on M(R(X)) and F8-FF on the immediate byte following the instruction, except
+    // for the shifts F6 and FE
+    fn step_arithmetic(&mut self, n: u8, bus: &mut impl Bus) {
+        let value = match n {
+            0x6 | 0xE => 0,
+            0x8..=0xF => self.fetch(bus),
+            _ => bus.read(self.rx()),
+        };
+        match n & 0x7 {
+            0x0 => self.d = value,
+            0x1 => self.d |= value,
+            0x2 => self.d &= value,
+            0x3 => self.d ^= value,
+            0x4 => self.add(value, self.d, false),
+            0x5 => self.subtract(value, self.d, false),
+            0x6 if n == 0x6 => {
+                self.df = self.d & 1 == 1;
+                self.d >>= 1;
+            },
+            0x6 => {
+                self.df = self.d & 0x80 != 0;
+                self.d <<= 1;
+            },
+            _ => self.subtract(self.d, value, false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestBus {
+        memory: Vec<u8>,
+        output: Vec<(u8, u8)>,
+        ef: [bool; 4],
+    }
+
+    impl TestBus {
+        fn new(program: &[u8]) -> Self {
+            let mut memory = vec![0; 0x10000];
+            memory[..program.len()].copy_from_slice(program);
+            Self { memory, output: Vec::new(), ef: [false; 4] }
+        }
+    }
+
+    impl Bus for TestBus {
+        fn read(&mut self, addr: u16) -> u8 {
+            self.memory[addr as usize]
+        }
+
+        fn write(&mut self, addr: u16, value: u8) {
+            self.memory[addr as usize] = value;
+        }
+
+        fn input(&mut self, port: u8) -> u8 {
+            port * 0x10
+        }
+
+        fn output(&mut self, port: u8, value: u8) {
+            self.output.push((port, value));
+        }
+
+        fn ef(&self, flag: u8) -> bool {
+            self.ef[flag as usize - 1]
+        }
+    }
+
+    fn run(cpu: &mut Cdp1802, bus: &mut TestBus, instructions: usize) -> u32 {
+        (0..instructions).map(|_| cpu.step(bus)).sum()
+    }
+
+    #[test]
+    fn test_arithmetic_and_flags() {
+        // LDI F0; ADI 20; LDI 10; SMI 20; SHL
+        let mut bus = TestBus::new(&[0xF8, 0xF0, 0xFC, 0x20, 0xF8, 0x10, 0xFF, 0x20, 0xFE]);
+        let mut cpu = Cdp1802::new();
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!((cpu.d, cpu.df), (0x10, true));
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!((cpu.d, cpu.df), (0xF0, false));
+        run(&mut cpu, &mut bus, 1);
+        assert_eq!((cpu.d, cpu.df), (0xE0, true));
+        assert_eq!(cpu.r[0], 9);
+    }
+
+    #[test]
+    fn test_register_ops_and_memory() {
+        // LDI 12; PHI 5; LDI 34; PLO 5; LDI AB; STR 5; LDA 5; INC 5; GLO 5
+        let mut bus = TestBus::new(&[0xF8, 0x12, 0xB5, 0xF8, 0x34, 0xA5, 0xF8, 0xAB, 0x55, 0xF8, 0x00, 0x45, 0x15, 0x85]);
+        let mut cpu = Cdp1802::new();
+        run(&mut cpu, &mut bus, 6);
+        assert_eq!(bus.memory[0x1234], 0xAB);
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!((cpu.d, cpu.r[5]), (0xAB, 0x1235));
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!(cpu.d, 0x36);
+    }
+
+    #[test]
+    fn test_branches() {
+        // BZ 06 ... B1 00; LBR 1234
+        let mut bus = TestBus::new(&[0x32, 0x06, 0x00, 0x00, 0x00, 0x00, 0x34, 0x00, 0xC0, 0x12, 0x34]);
+        let mut cpu = Cdp1802::new();
+        cpu.step(&mut bus);
+        assert_eq!(cpu.r[0], 0x06);
+        cpu.step(&mut bus);
+        assert_eq!(cpu.r[0], 0x08);
+        assert_eq!(cpu.step(&mut bus), LONG_INSTRUCTION_CYCLES);
+        assert_eq!(cpu.r[0], 0x1234);
+
+        bus.ef[0] = true;
+        cpu.r[0] = 6;
+        cpu.step(&mut bus);
+        assert_eq!(cpu.r[0], 0x00);
+    }
+
+    #[test]
+    fn test_long_skips() {
+        // LSZ; (skipped); LSNZ; (not skipped)
+        let mut bus = TestBus::new(&[0xCE, 0x00, 0x00, 0xC6, 0x7B]);
+        let mut cpu = Cdp1802::new();
+        cpu.step(&mut bus);
+        assert_eq!(cpu.r[0], 3);
+        cpu.step(&mut bus);
+        cpu.step(&mut bus);
+        assert!(cpu.q);
+    }
+
+    #[test]
+    fn test_sep_mark_and_ret() {
+        let mut bus = TestBus::new(&[]);
+        // At 0x100 with P = 3: MARK; SEP 4. At 0x200 with P = 4: IRX; RET
+        bus.memory[0x100..0x102].copy_from_slice(&[0x79, 0xD4]);
+        bus.memory[0x200..0x202].copy_from_slice(&[0x60, 0x70]);
+        let mut cpu = Cdp1802::new();
+        cpu.p = 3;
+        cpu.x = 5;
+        cpu.r[2] = 0x80;
+        cpu.r[3] = 0x100;
+        cpu.r[4] = 0x200;
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!((cpu.p, cpu.x, cpu.r[2]), (4, 3, 0x7F));
+        assert_eq!(bus.memory[0x80], 0x53);
+        cpu.x = 2;
+        run(&mut cpu, &mut bus, 2);
+        assert_eq!((cpu.p, cpu.x, cpu.r[2], cpu.r[3]), (3, 5, 0x81, 0x102));
+    }
+
+    #[test]
+    fn test_input_output() {
+        // SEX 1; OUT 2; INP 3
+        let mut bus = TestBus::new(&[0xE1, 0x62, 0x6B]);
+        bus.memory[0x40] = 0x77;
+        let mut cpu = Cdp1802::new();
+        cpu.r[1] = 0x40;
+        run(&mut cpu, &mut bus, 3);
+        assert_eq!(bus.output, vec![(2, 0x77)]);
+        assert_eq!((cpu.d, bus.memory[0x41]), (0x30, 0x30));
+    }
+
+    #[test]
+    fn test_interrupt_idle_and_dma() {
+        // IDL
+        let mut bus = TestBus::new(&[0x00]);
+        bus.memory[0x300] = 0x5A;
+        let mut cpu = Cdp1802::new();
+        cpu.p = 3;
+        cpu.r[3] = 0;
+        cpu.step(&mut bus);
+        assert_eq!(cpu.step(&mut bus), SHORT_INSTRUCTION_CYCLES);
+        assert_eq!(cpu.r[3], 1);
+
+        cpu.r[0] = 0x300;
+        assert_eq!(cpu.dma_out(&mut bus), 0x5A);
+        assert_eq!(cpu.r[0], 0x301);
+
+        assert!(cpu.interrupt());
+        assert_eq!((cpu.p, cpu.x, cpu.t, cpu.ie), (1, 2, 0x03, false));
+        assert!(!cpu.interrupt());
+    }
+}
@@ -0,0 +1,130 @@
+use std::ops::Range;
+
+// NTSC timing of the 1861 in 1802 machine cycles
+pub const CYCLES_PER_LINE: u32 = 14;
+pub const LINES_PER_FRAME: u32 = 262;
+pub const CYCLES_PER_FRAME: u32 = CYCLES_PER_LINE * LINES_PER_FRAME;
+// The interrupt comes two lines, 29 cycles counting the interrupt cycle, before the first DMA
+const INTERRUPT_LINE: u32 = 78;
+const FIRST_DISPLAY_LINE: u32 = 80;
+pub const DISPLAY_LINES: usize = 128;
+pub const BYTES_PER_LINE: usize = 8;
+pub const DISPLAY_WIDTH: usize = BYTES_PER_LINE * 8;
+// EF1 goes active for the four lines before and the last four lines of the display area
+const EF1_LINES: [Range<u32>; 2] = [76..80, 204..208];
+
+// The RCA CDP1861 "Pixie" video chip. Each display line it takes eight bytes from the CPU by
+// DMA, starting at wherever the display interrupt routine left R0.
+#[derive(Debug, Clone)]
+pub struct Cdp1861 {
+    enabled: bool,
+    cycle: u32,
+    frames: u64,
+    next_dma_line: usize,
+    interrupt_taken: bool,
+    lines: [[u8; BYTES_PER_LINE]; DISPLAY_LINES],
+}
+
+impl Cdp1861 {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            cycle: 0,
+            frames: 0,
+            next_dma_line: 0,
+            interrupt_taken: false,
+            lines: [[0; BYTES_PER_LINE]; DISPLAY_LINES],
+        }
+    }
+
+    // INP 1 turns the display on and OUT 1 turns it off
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    fn line(&self) -> u32 {
+        self.cycle / CYCLES_PER_LINE
+    }
+
+    pub fn ef1(&self) -> bool {
+        let line = self.line();
+        self.enabled && EF1_LINES.iter().any(|lines| lines.contains(&line))
+    }
+
+    pub fn interrupt_due(&self) -> bool {
+        self.enabled && !self.interrupt_taken && (INTERRUPT_LINE..FIRST_DISPLAY_LINE).contains(&self.line())
+    }
+
+    pub fn acknowledge_interrupt(&mut self) {
+        self.interrupt_taken = true;
+    }
+
+    // The display line waiting for its DMA, if any
+    pub fn dma_due(&self) -> Option<usize> {
+        let line = self.line().checked_sub(FIRST_DISPLAY_LINE)? as usize;
+        (self.enabled && line < DISPLAY_LINES && line >= self.next_dma_line).then_some(line)
+    }
+
+    pub fn store_line(&mut self, line: usize, bytes: [u8; BYTES_PER_LINE]) {
+        self.lines[line] = bytes;
+        self.next_dma_line = line + 1;
+    }
+
+    pub fn advance(&mut self, cycles: u32) {
+        self.cycle += cycles;
+        if self.cycle >= CYCLES_PER_FRAME {
+            self.cycle -= CYCLES_PER_FRAME;
+            self.frames += 1;
+            self.next_dma_line = 0;
+            self.interrupt_taken = false;
+            if !self.enabled {
+                self.lines = [[0; BYTES_PER_LINE]; DISPLAY_LINES];
+            }
+        }
+    }
+
+    pub fn pixel(&self, x: usize, line: usize) -> bool {
+        (self.lines[line][x / 8] >> (7 - x % 8)) & 1 == 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_timing() {
+        let mut video = Cdp1861::new();
+        video.set_enabled(true);
+        video.advance(INTERRUPT_LINE * CYCLES_PER_LINE - 1);
+        assert!(!video.interrupt_due() && video.ef1());
+        video.advance(1);
+        assert!(video.interrupt_due());
+        video.acknowledge_interrupt();
+        assert!(!video.interrupt_due());
+
+        video.advance(2 * CYCLES_PER_LINE);
+        assert_eq!(video.dma_due(), Some(0));
+        video.store_line(0, [0x80; BYTES_PER_LINE]);
+        assert_eq!(video.dma_due(), None);
+        assert!(video.pixel(0, 0) && !video.pixel(1, 0));
+
+        video.advance(CYCLES_PER_FRAME - 2 * CYCLES_PER_LINE);
+        assert_eq!(video.frames(), 1);
+        assert!(video.interrupt_due());
+    }
+
+    #[test]
+    fn test_disabled_display() {
+        let mut video = Cdp1861::new();
+        video.store_line(5, [0xFF; BYTES_PER_LINE]);
+        video.advance(FIRST_DISPLAY_LINE * CYCLES_PER_LINE);
+        assert!(!video.interrupt_due() && video.dma_due().is_none() && !video.ef1());
+        video.advance(CYCLES_PER_FRAME);
+        assert!(!video.pixel(0, 5));
+    }
+}